@@ -49,4 +49,64 @@ pub enum ErrorCode {
     InvalidLoanIndex,
     #[msg("Insufficient swap proceeds")]
     InsufficientSwapProceeds,
+    #[msg("Missing borrower token account")]
+    MissingBorrowerAccount,
+    #[msg("Missing self-trade refund account")]
+    MissingRefundAccount,
+    #[msg("Invalid market configuration")]
+    InvalidMarketConfig,
+    #[msg("Order size below market minimum")]
+    OrderTooSmall,
+    #[msg("Order size above market maximum")]
+    OrderTooLarge,
+    #[msg("Invalid delegate permissions")]
+    InvalidDelegatePermissions,
+    #[msg("Delegate lacks the required permission")]
+    DelegatePermissionDenied,
+    #[msg("Delegate has expired")]
+    DelegateExpired,
+    #[msg("Delegate spend cap exceeded")]
+    DelegateSpendCapExceeded,
+    #[msg("Invalid order index")]
+    InvalidOrderIndex,
+    #[msg("Insufficient maker balance")]
+    InsufficientBalance,
+    #[msg("Missing or invalid Ed25519 signature instruction")]
+    InvalidSignature,
+    #[msg("Signed order has expired")]
+    OrderExpired,
+    #[msg("Signed order nonce already used or cancelled")]
+    NonceAlreadyUsed,
+    #[msg("Pool still holds orders or live loans")]
+    PoolNotEmpty,
+    #[msg("Missing or invalid loan account")]
+    InvalidLoanAccount,
+    #[msg("Missing or invalid user positions account")]
+    InvalidPositionsAccount,
+    #[msg("Loan is not past maturity plus grace period")]
+    GracePeriodActive,
+    #[msg("Loan status does not allow this action")]
+    InvalidLoanStatus,
+    #[msg("Partial repayment must be less than the amount due")]
+    PartialRepaymentTooLarge,
+    #[msg("Minimum interest slots must be positive and within the loan duration")]
+    InvalidEarlyRepayment,
+    #[msg("Bid rate exceeds the borrower's maximum rate")]
+    RateTooHigh,
+    #[msg("Refinancing bid belongs to the current lender")]
+    RefinanceSameLender,
+    #[msg("Missing or invalid loan position token account")]
+    InvalidPositionToken,
+    #[msg("Loan is already tokenized")]
+    LoanAlreadyTokenized,
+    #[msg("Loan has no claimable balance")]
+    NothingToClaim,
+    #[msg("Loan has a claimable balance that must be claimed first")]
+    UnclaimedBalance,
+    #[msg("Invalid collateral basket")]
+    InvalidBasket,
+    #[msg("Missing or invalid collateral config account")]
+    InvalidCollateralConfig,
+    #[msg("Missing or invalid basket token account")]
+    InvalidBasketAccount,
 }
@@ -1,5 +1,7 @@
 use anchor_lang::prelude::*;
 
+use crate::states::{CollateralComponent, OrderSide, SpendCap};
+
 // Events
 #[event]
 pub struct AuctionInitialized {
@@ -8,6 +10,33 @@ pub struct AuctionInitialized {
     pub supported_tokens: Vec<Pubkey>,
 }
 
+#[event]
+pub struct MarketConfigured {
+    pub admin: Pubkey,
+    pub token_mint: Pubkey,
+    pub min_amount: u64,
+    pub max_amount: u64,
+    pub grace_period: i64,
+    pub penalty_rate: u8,
+    pub initial_collateral_ratio: u16,
+}
+
+#[event]
+pub struct CollateralConfigured {
+    pub admin: Pubkey,
+    pub mint: Pubkey,
+    pub haircut_bps: u16,
+    pub min_collateral: u64,
+    pub max_collateral: u64,
+}
+
+#[event]
+pub struct FeeVaultCreated {
+    pub admin: Pubkey,
+    pub mint: Pubkey,
+    pub fee_vault: Pubkey,
+}
+
 #[event]
 pub struct BidSubmitted {
     pub lender: Pubkey,
@@ -25,10 +54,12 @@ pub struct AskSubmitted {
     pub shard_id: u64,
     pub token_mint: Pubkey,
     pub collateral_mint: Pubkey,
+    pub basket: Vec<CollateralComponent>,
 }
 
 #[event]
 pub struct LoanIssued {
+    pub loan_id: u64,
     pub lender: Pubkey,
     pub borrower: Pubkey,
     pub amount: u64,
@@ -38,11 +69,125 @@ pub struct LoanIssued {
     pub collateral_mint: Pubkey,
 }
 
+#[event]
+pub struct OrdersMatched {
+    pub cranker: Pubkey,
+    pub shard_id: u64,
+    pub matches: u64,
+    pub volume: u64,
+    pub reward: u64,
+    pub token_mint: Pubkey,
+}
+
+#[event]
+pub struct SelfTradePrevented {
+    pub owner: Pubkey,
+    pub side: OrderSide,
+    pub resting: bool,
+    pub amount: u64,
+    pub collateral: u64,
+    pub shard_id: u64,
+    pub token_mint: Pubkey,
+}
+
 #[event]
 pub struct LoanRepaid {
+    pub loan_id: u64,
     pub lender: Pubkey,
     pub borrower: Pubkey,
+    pub payer: Pubkey,
     pub amount: u64,
+    pub penalty: u64,
+    pub shard_id: u64,
+    pub token_mint: Pubkey,
+    pub collateral_mint: Pubkey,
+}
+
+#[event]
+pub struct LoanPartiallyRepaid {
+    pub loan_id: u64,
+    pub lender: Pubkey,
+    pub borrower: Pubkey,
+    pub interest_paid: u64,
+    pub principal_paid: u64,
+    pub remaining_amount: u64,
+    pub collateral_released: u64,
+    pub shard_id: u64,
+    pub token_mint: Pubkey,
+}
+
+#[event]
+pub struct CollateralAdded {
+    pub loan_id: u64,
+    pub borrower: Pubkey,
+    pub amount: u64,
+    pub collateral: u64,
+    pub shard_id: u64,
+    pub collateral_mint: Pubkey,
+}
+
+#[event]
+pub struct CollateralWithdrawn {
+    pub loan_id: u64,
+    pub borrower: Pubkey,
+    pub amount: u64,
+    pub collateral: u64,
+    pub health_factor: u64,
+    pub shard_id: u64,
+    pub collateral_mint: Pubkey,
+}
+
+#[event]
+pub struct LoanRefinanced {
+    pub old_loan_id: u64,
+    pub new_loan_id: u64,
+    pub borrower: Pubkey,
+    pub old_lender: Pubkey,
+    pub new_lender: Pubkey,
+    pub payoff: u64,
+    pub rate: u8,
+    pub shard_id: u64,
+    pub token_mint: Pubkey,
+}
+
+#[event]
+pub struct LoanExtended {
+    pub loan_id: u64,
+    pub lender: Pubkey,
+    pub borrower: Pubkey,
+    pub old_maturity_ts: i64,
+    pub new_maturity_ts: i64,
+    pub old_rate: u8,
+    pub new_rate: u8,
+    pub accrued_interest: u64,
+    pub accrued_penalty: u64,
+}
+
+#[event]
+pub struct LoanTokenized {
+    pub loan_id: u64,
+    pub lender: Pubkey,
+    pub position_mint: Pubkey,
+}
+
+#[event]
+pub struct LenderClaimed {
+    pub loan_id: u64,
+    pub lender: Pubkey,
+    pub amount: u64,
+    pub closed: bool,
+    pub token_mint: Pubkey,
+}
+
+#[event]
+pub struct LoanRepaidWithCollateral {
+    pub loan_id: u64,
+    pub lender: Pubkey,
+    pub borrower: Pubkey,
+    pub amount: u64,
+    pub penalty: u64,
+    pub collateral_sold: u64,
+    pub collateral_returned: u64,
     pub shard_id: u64,
     pub token_mint: Pubkey,
     pub collateral_mint: Pubkey,
@@ -50,17 +195,45 @@ pub struct LoanRepaid {
 
 #[event]
 pub struct LoanLiquidated {
+    pub loan_id: u64,
     pub lender: Pubkey,
     pub borrower: Pubkey,
     pub liquidator: Pubkey,
     pub amount: u64,
     pub collateral: u64,
     pub profit: u64,
+    /// Whether this sale covered the debt and ended the liquidation
+    pub settled: bool,
+    /// Balance written off when the loan ran out of collateral before its debt was covered
+    pub shortfall: u64,
     pub shard_id: u64,
     pub token_mint: Pubkey,
     pub collateral_mint: Pubkey,
 }
 
+#[event]
+pub struct LoanDefaulted {
+    pub loan_id: u64,
+    pub lender: Pubkey,
+    pub borrower: Pubkey,
+    pub amount: u64,
+    pub collateral: u64,
+    pub maturity_ts: i64,
+    pub shard_id: u64,
+    pub token_mint: Pubkey,
+    pub collateral_mint: Pubkey,
+}
+
+#[event]
+pub struct CollateralClaimed {
+    pub loan_id: u64,
+    pub lender: Pubkey,
+    pub borrower: Pubkey,
+    pub collateral: u64,
+    pub shard_id: u64,
+    pub collateral_mint: Pubkey,
+}
+
 #[event]
 pub struct BidExpired {
     pub lender: Pubkey,
@@ -82,10 +255,74 @@ pub struct AskExpired {
     pub collateral_mint: Pubkey,
 }
 
+#[event]
+pub struct DelegateApproved {
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    pub permissions: u8,
+    pub spend_caps: Vec<SpendCap>,
+    pub expiry_slot: u64,
+}
+
+#[event]
+pub struct DelegateRevoked {
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+}
+
+#[event]
+pub struct OrderCancelled {
+    pub owner: Pubkey,
+    pub authority: Pubkey,
+    pub side: OrderSide,
+    pub amount: u64,
+    pub collateral: u64,
+    pub shard_id: u64,
+    pub token_mint: Pubkey,
+}
+
+#[event]
+pub struct BalanceDeposited {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub balance: u64,
+}
+
+#[event]
+pub struct BalanceWithdrawn {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub balance: u64,
+}
+
+#[event]
+pub struct NoncesCancelled {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub min_nonce: u64,
+}
+
+#[event]
+pub struct SignedOrderSettled {
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub side: OrderSide,
+    pub nonce: u64,
+    pub shard_id: u64,
+}
+
+#[event]
+pub struct PoolsClosed {
+    pub shard_id: u64,
+    pub shard_pool_payer: Pubkey,
+    pub loan_pool_payer: Pubkey,
+}
+
 #[event]
 pub struct FeesWithdrawn {
     pub admin: Pubkey,
-    pub shard_id: u64,
     pub amount: u64,
     pub token_mint: Pubkey,
 }
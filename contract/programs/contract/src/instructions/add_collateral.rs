@@ -0,0 +1,91 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer, Token, TokenAccount, Transfer};
+
+use crate::{
+    errors::ErrorCode,
+    events::CollateralAdded,
+    states::{LendAuction, Loan, LoanStatus, UserPositions},
+    utils::post_collateral,
+};
+
+/// Borrower tops up the collateral of an active loan
+pub fn process_add_collateral(
+    ctx: Context<AddCollateral>,
+    loan_id: u64,
+    amount: u64,
+) -> Result<()> {
+    let loan = &mut ctx.accounts.loan;
+
+    require!(amount > 0, ErrorCode::InvalidCollateral);
+    require!(
+        loan.status == LoanStatus::Active,
+        ErrorCode::InvalidLoanStatus
+    );
+    require_keys_eq!(
+        loan.borrower,
+        ctx.accounts.borrower.key(),
+        ErrorCode::Unauthorized
+    );
+    require_eq!(
+        ctx.accounts.borrower_collateral_account.mint,
+        loan.collateral_mint,
+        ErrorCode::InvalidTokenAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_collateral_account.mint,
+        loan.collateral_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.borrower_collateral_account.to_account_info(),
+                to: ctx.accounts.vault_collateral_account.to_account_info(),
+                authority: ctx.accounts.borrower.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+
+    loan.collateral = loan
+        .collateral
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+    post_collateral(
+        &mut ctx.accounts.borrower_positions,
+        &loan.collateral_mint,
+        amount,
+    )?;
+
+    emit!(CollateralAdded {
+        loan_id,
+        borrower: loan.borrower,
+        amount,
+        collateral: loan.collateral,
+        shard_id: loan.shard_id,
+        collateral_mint: loan.collateral_mint,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct AddCollateral<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(mut, seeds = [b"loan", loan_id.to_le_bytes().as_ref()], bump)]
+    pub loan: Account<'info, Loan>,
+    #[account(mut, seeds = [b"user_positions", loan.borrower.as_ref()], bump)]
+    pub borrower_positions: Box<Account<'info, UserPositions>>,
+    pub borrower: Signer<'info>,
+    #[account(mut, constraint = borrower_collateral_account.owner == borrower.key())]
+    pub borrower_collateral_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_collateral_account.owner == lend_auction.key()
+    )]
+    pub vault_collateral_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
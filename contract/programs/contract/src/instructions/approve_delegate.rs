@@ -0,0 +1,77 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::ErrorCode;
+use crate::events::DelegateApproved;
+use crate::states::{
+    Delegate, SpendCap, DELEGATE_CANCEL_ORDERS, DELEGATE_PLACE_ORDERS, DELEGATE_REPAY,
+    MAX_DELEGATE_MINTS,
+};
+
+/// Owner grants a delegate key limited trading rights with a spend cap per mint, resetting its spend.
+/// Tokens a delegate commits move under the lend auction's authority, so the owner approves the
+/// lend auction, not the delegate key, on its token accounts.
+pub fn process_approve_delegate(
+    ctx: Context<ApproveDelegate>,
+    delegate: Pubkey,
+    permissions: u8,
+    mut spend_caps: Vec<SpendCap>,
+    expiry_slot: u64,
+) -> Result<()> {
+    let owner = &ctx.accounts.owner;
+
+    require_keys_neq!(delegate, owner.key(), ErrorCode::InvalidDelegatePermissions);
+    require!(
+        permissions != 0
+            && permissions & !(DELEGATE_PLACE_ORDERS | DELEGATE_CANCEL_ORDERS | DELEGATE_REPAY)
+                == 0,
+        ErrorCode::InvalidDelegatePermissions
+    );
+    require!(expiry_slot > Clock::get()?.slot, ErrorCode::DelegateExpired);
+    require!(
+        spend_caps.len() <= MAX_DELEGATE_MINTS,
+        ErrorCode::InvalidDelegatePermissions
+    );
+    for (i, spend_cap) in spend_caps.iter().enumerate() {
+        require!(
+            spend_caps[..i]
+                .iter()
+                .all(|other| other.mint != spend_cap.mint),
+            ErrorCode::InvalidDelegatePermissions
+        );
+    }
+    for spend_cap in spend_caps.iter_mut() {
+        spend_cap.spent = 0;
+    }
+
+    let delegate_account = &mut ctx.accounts.delegate_account;
+    delegate_account.owner = owner.key();
+    delegate_account.delegate = delegate;
+    delegate_account.permissions = permissions;
+    delegate_account.spend_caps = spend_caps.clone();
+    delegate_account.expiry_slot = expiry_slot;
+
+    emit!(DelegateApproved {
+        owner: owner.key(),
+        delegate,
+        permissions,
+        spend_caps,
+        expiry_slot,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey)]
+pub struct ApproveDelegate<'info> {
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = Delegate::SPACE,
+        seeds = [b"delegate", owner.key().as_ref(), delegate.as_ref()],
+        bump
+    )]
+    pub delegate_account: Account<'info, Delegate>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
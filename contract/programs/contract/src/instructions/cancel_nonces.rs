@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::ErrorCode;
+use crate::events::NoncesCancelled;
+use crate::states::MakerBalance;
+
+/// Revoke every unfilled signed order funded by this balance with a nonce below `min_nonce`
+pub fn process_cancel_nonces(ctx: Context<CancelNonces>, min_nonce: u64) -> Result<()> {
+    let maker_balance = &mut ctx.accounts.maker_balance;
+
+    require_gt!(
+        min_nonce,
+        maker_balance.min_nonce,
+        ErrorCode::NonceAlreadyUsed
+    );
+    maker_balance.min_nonce = min_nonce;
+
+    emit!(NoncesCancelled {
+        owner: maker_balance.owner,
+        mint: maker_balance.mint,
+        min_nonce,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CancelNonces<'info> {
+    #[account(
+        mut,
+        seeds = [b"maker_balance", owner.key().as_ref(), maker_balance.mint.as_ref()],
+        bump
+    )]
+    pub maker_balance: Account<'info, MakerBalance>,
+    pub owner: Signer<'info>,
+}
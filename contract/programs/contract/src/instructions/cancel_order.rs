@@ -0,0 +1,170 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer, Token, TokenAccount, Transfer};
+
+use crate::errors::ErrorCode;
+use crate::events::OrderCancelled;
+use crate::states::{
+    Delegate, LendAuction, OrderSide, ShardPool, UserPositions, DELEGATE_CANCEL_ORDERS,
+};
+use crate::utils::{authorize_delegate, refund_delegate, sync_order_shards, transfer_basket};
+
+/// Cancel a resting bid or ask and refund its deposit to the owner.
+/// An ask's basket is refunded between the associated token accounts passed in remaining accounts.
+/// When `order_delegate` is the delegate that placed the order, its spend cap is refunded too.
+pub fn process_cancel_order<'info>(
+    ctx: Context<'_, '_, 'info, 'info, CancelOrder<'info>>,
+    shard_id: u64,
+    side: OrderSide,
+    order_idx: u64,
+) -> Result<()> {
+    let shard_pool = &mut ctx.accounts.shard_pool;
+    let lend_auction = &ctx.accounts.lend_auction;
+    let owner = ctx.accounts.owner.key();
+    let authority = ctx.accounts.authority.key();
+
+    require!(shard_id < lend_auction.shard_count, ErrorCode::InvalidShard);
+    require_eq!(shard_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+    authorize_delegate(
+        &owner,
+        &authority,
+        ctx.accounts.delegate.as_mut(),
+        DELEGATE_CANCEL_ORDERS,
+        &[],
+    )?;
+
+    // Remove the order and work out which deposit to refund
+    let (refund_mint, refund_amount, amount, collateral, token_mint, basket, spend, placed_by) =
+        match side {
+            OrderSide::Bid => {
+                require!(
+                    order_idx < shard_pool.bids.len() as u64,
+                    ErrorCode::InvalidOrderIndex
+                );
+                require_keys_eq!(
+                    shard_pool.bids[order_idx as usize].lender,
+                    owner,
+                    ErrorCode::Unauthorized
+                );
+                let bid = shard_pool.bids.remove(order_idx as usize);
+                (
+                    bid.token_mint,
+                    bid.amount,
+                    bid.amount,
+                    0,
+                    bid.token_mint,
+                    Vec::new(),
+                    bid.spend(),
+                    bid.delegate,
+                )
+            }
+            OrderSide::Ask => {
+                require!(
+                    order_idx < shard_pool.asks.len() as u64,
+                    ErrorCode::InvalidOrderIndex
+                );
+                require_keys_eq!(
+                    shard_pool.asks[order_idx as usize].borrower,
+                    owner,
+                    ErrorCode::Unauthorized
+                );
+                let ask = shard_pool.asks.remove(order_idx as usize);
+                let spend = ask.spend();
+                (
+                    ask.collateral_mint,
+                    ask.collateral,
+                    ask.amount,
+                    ask.collateral,
+                    ask.token_mint,
+                    ask.basket,
+                    spend,
+                    ask.delegate,
+                )
+            }
+        };
+
+    sync_order_shards(&mut ctx.accounts.owner_positions, shard_pool)?;
+
+    if let Some(order_delegate) = ctx.accounts.order_delegate.as_mut() {
+        require!(
+            placed_by == Some(order_delegate.key()),
+            ErrorCode::Unauthorized
+        );
+        refund_delegate(order_delegate, &spend);
+    }
+
+    require_eq!(
+        ctx.accounts.owner_token_account.mint,
+        refund_mint,
+        ErrorCode::InvalidTokenAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_account.mint,
+        refund_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.vault_account.to_account_info(),
+                to: ctx.accounts.owner_token_account.to_account_info(),
+                authority: lend_auction.to_account_info(),
+            },
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+        ),
+        refund_amount,
+    )?;
+    transfer_basket(
+        ctx.remaining_accounts,
+        &basket,
+        &lend_auction.key(),
+        &owner,
+        &lend_auction.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+    )?;
+
+    emit!(OrderCancelled {
+        owner,
+        authority,
+        side,
+        amount,
+        collateral,
+        shard_id,
+        token_mint,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(shard_id: u64)]
+pub struct CancelOrder<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(mut, seeds = [b"shard_pool", shard_id.to_le_bytes().as_ref()], bump)]
+    pub shard_pool: Account<'info, ShardPool>,
+    pub authority: Signer<'info>,
+    /// CHECK: Order owner, validated against the order and the signing authority
+    pub owner: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"delegate", owner.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>,
+    /// Delegate that placed the order, declared after `delegate` so its refund is the one persisted
+    /// when both are the same account
+    #[account(mut)]
+    pub order_delegate: Option<Account<'info, Delegate>>,
+    #[account(mut, seeds = [b"user_positions", owner.key().as_ref()], bump)]
+    pub owner_positions: Account<'info, UserPositions>,
+    #[account(mut, constraint = owner_token_account.owner == owner.key())]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_account.owner == lend_auction.key()
+    )]
+    pub vault_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
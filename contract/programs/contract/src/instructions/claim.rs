@@ -0,0 +1,97 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer, Token, TokenAccount, Transfer};
+
+use crate::{
+    errors::ErrorCode,
+    events::LenderClaimed,
+    states::{LendAuction, Loan, LoanPool},
+    utils::lender_side_owner,
+};
+
+/// Lender side withdraws a loan's claimable balance from the vault.
+/// Once a repaid or liquidated loan is fully claimed, its account is closed.
+pub fn process_claim(ctx: Context<Claim>, loan_id: u64) -> Result<()> {
+    let loan = &mut ctx.accounts.loan;
+    let lend_auction = &ctx.accounts.lend_auction;
+
+    require_keys_eq!(
+        lender_side_owner(loan, ctx.accounts.position_token_account.as_deref())?,
+        ctx.accounts.lender.key(),
+        ErrorCode::Unauthorized
+    );
+    require!(
+        loan.claimable > 0 || !loan.is_open(),
+        ErrorCode::NothingToClaim
+    );
+    require_eq!(
+        ctx.accounts.lender_token_account.mint,
+        loan.token_mint,
+        ErrorCode::InvalidTokenAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_token_account.mint,
+        loan.token_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    let amount = loan.claimable;
+    if amount > 0 {
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault_token_account.to_account_info(),
+                    to: ctx.accounts.lender_token_account.to_account_info(),
+                    authority: lend_auction.to_account_info(),
+                },
+                &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+            ),
+            amount,
+        )?;
+    }
+    loan.claimable = 0;
+
+    // Settled loans have nothing left to track once paid out
+    let closed = !loan.is_open();
+    if closed {
+        let loan_pool = &mut ctx.accounts.loan_pool;
+        loan_pool.active_loans = loan_pool
+            .active_loans
+            .checked_sub(1)
+            .ok_or(ErrorCode::Overflow)?;
+        loan.close(ctx.accounts.loan_payer.to_account_info())?;
+    }
+
+    emit!(LenderClaimed {
+        loan_id,
+        lender: ctx.accounts.lender.key(),
+        amount,
+        closed,
+        token_mint: loan.token_mint,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct Claim<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(mut, seeds = [b"loan", loan_id.to_le_bytes().as_ref()], bump)]
+    pub loan: Account<'info, Loan>,
+    #[account(mut, seeds = [b"loan_pool", loan.shard_id.to_le_bytes().as_ref()], bump)]
+    pub loan_pool: Account<'info, LoanPool>,
+    /// CHECK: Rent payer recorded on the loan at creation
+    #[account(mut, address = loan.payer)]
+    pub loan_payer: UncheckedAccount<'info>,
+    pub lender: Signer<'info>,
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, constraint = lender_token_account.owner == lender.key())]
+    pub lender_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_token_account.owner == lend_auction.key()
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
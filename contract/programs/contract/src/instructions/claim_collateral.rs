@@ -0,0 +1,113 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer, Token, TokenAccount, Transfer};
+
+use crate::{
+    errors::ErrorCode,
+    events::CollateralClaimed,
+    states::{LendAuction, Loan, LoanPool, LoanStatus, UserPositions},
+    utils::{close_loan_position, lender_side_owner, transfer_basket},
+};
+
+/// Lender takes the full collateral of a defaulted loan and closes its account.
+/// Basket components move to the lender's associated token accounts passed in remaining accounts.
+pub fn process_claim_collateral<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ClaimCollateral<'info>>,
+    loan_id: u64,
+) -> Result<()> {
+    let loan_pool = &mut ctx.accounts.loan_pool;
+    let loan = &ctx.accounts.loan;
+    let lend_auction = &ctx.accounts.lend_auction;
+
+    require!(
+        loan.status == LoanStatus::Defaulted,
+        ErrorCode::InvalidLoanStatus
+    );
+    require_eq!(loan.claimable, 0, ErrorCode::UnclaimedBalance);
+    require_keys_eq!(
+        lender_side_owner(loan, ctx.accounts.position_token_account.as_deref())?,
+        ctx.accounts.lender.key(),
+        ErrorCode::Unauthorized
+    );
+    require_eq!(
+        ctx.accounts.lender_collateral_account.mint,
+        loan.collateral_mint,
+        ErrorCode::InvalidTokenAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_collateral_account.mint,
+        loan.collateral_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.vault_collateral_account.to_account_info(),
+                to: ctx.accounts.lender_collateral_account.to_account_info(),
+                authority: lend_auction.to_account_info(),
+            },
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+        ),
+        loan.collateral,
+    )?;
+    transfer_basket(
+        ctx.remaining_accounts,
+        &loan.basket,
+        &lend_auction.key(),
+        &ctx.accounts.lender.key(),
+        &lend_auction.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+    )?;
+
+    close_loan_position(&mut ctx.accounts.lender_positions, loan)?;
+    close_loan_position(&mut ctx.accounts.borrower_positions, loan)?;
+    loan_pool.active_loans = loan_pool
+        .active_loans
+        .checked_sub(1)
+        .ok_or(ErrorCode::Overflow)?;
+
+    emit!(CollateralClaimed {
+        loan_id,
+        lender: loan.lender,
+        borrower: loan.borrower,
+        collateral: loan.collateral,
+        shard_id: loan.shard_id,
+        collateral_mint: loan.collateral_mint,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct ClaimCollateral<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(
+        mut,
+        close = loan_payer,
+        seeds = [b"loan", loan_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub loan: Account<'info, Loan>,
+    #[account(mut, seeds = [b"loan_pool", loan.shard_id.to_le_bytes().as_ref()], bump)]
+    pub loan_pool: Account<'info, LoanPool>,
+    /// CHECK: Rent payer recorded on the loan at creation
+    #[account(mut, address = loan.payer)]
+    pub loan_payer: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"user_positions", loan.lender.as_ref()], bump)]
+    pub lender_positions: Box<Account<'info, UserPositions>>,
+    #[account(mut, seeds = [b"user_positions", loan.borrower.as_ref()], bump)]
+    pub borrower_positions: Box<Account<'info, UserPositions>>,
+    pub lender: Signer<'info>,
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, constraint = lender_collateral_account.owner == lender.key())]
+    pub lender_collateral_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_collateral_account.owner == lend_auction.key()
+    )]
+    pub vault_collateral_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
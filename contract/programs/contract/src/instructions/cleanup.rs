@@ -4,9 +4,15 @@ use anchor_spl::token::{transfer, Token, TokenAccount, Transfer};
 use crate::errors::ErrorCode;
 use crate::events::{AskExpired, BidExpired};
 use crate::states::{LendAuction, ShardPool};
-
-/// Cleanup stale bids/asks with refunds and 0.5% fee
-pub fn process_cleanup(ctx: Context<Cleanup>, shard_id: u64) -> Result<()> {
+use crate::utils::{load_user_positions, sync_order_shards, transfer_basket};
+
+/// Cleanup stale bids/asks with refunds and 0.5% fee.
+/// Remaining accounts hold the position accounts of every owner with an expired order, and
+/// the associated token accounts of expired ask baskets, which are refunded without a fee.
+pub fn process_cleanup<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Cleanup<'info>>,
+    shard_id: u64,
+) -> Result<()> {
     let shard_pool = &mut ctx.accounts.shard_pool;
     let lend_auction = &ctx.accounts.lend_auction;
 
@@ -37,6 +43,20 @@ pub fn process_cleanup(ctx: Context<Cleanup>, shard_id: u64) -> Result<()> {
         }
     });
 
+    // Drop this shard from the position index of owners left without orders here
+    let mut owners: Vec<Pubkey> = refunded_bids
+        .iter()
+        .map(|bid| bid.lender)
+        .chain(refunded_asks.iter().map(|ask| ask.borrower))
+        .collect();
+    owners.sort();
+    owners.dedup();
+    for owner in owners {
+        let mut positions = load_user_positions(ctx.remaining_accounts, &owner)?;
+        sync_order_shards(&mut positions, shard_pool)?;
+        positions.exit(&crate::ID)?;
+    }
+
     for bid in refunded_bids {
         let refund_amount = bid
             .amount
@@ -133,6 +153,16 @@ pub fn process_cleanup(ctx: Context<Cleanup>, shard_id: u64) -> Result<()> {
             fee_amount,
         )?;
 
+        transfer_basket(
+            ctx.remaining_accounts,
+            &ask.basket,
+            &lend_auction.key(),
+            &ask.borrower,
+            &lend_auction.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+        )?;
+
         emit!(AskExpired {
             borrower: ask.borrower,
             amount: ask.amount,
@@ -168,12 +198,9 @@ pub struct Cleanup<'info> {
         constraint = vault_collateral_account.owner == lend_auction.key()
     )]
     pub vault_collateral_account: Account<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint = fee_vault.owner == lend_auction.key()
-    )]
+    #[account(mut, seeds = [b"fee_vault", fee_vault.mint.as_ref()], bump)]
     pub fee_vault: Account<'info, TokenAccount>,
-    #[account(mut, constraint = fee_vault_collateral.owner == lend_auction.key())]
+    #[account(mut, seeds = [b"fee_vault", fee_vault_collateral.mint.as_ref()], bump)]
     pub fee_vault_collateral: Account<'info, TokenAccount>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
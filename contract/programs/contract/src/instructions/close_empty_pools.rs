@@ -0,0 +1,59 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::ErrorCode;
+use crate::events::PoolsClosed;
+use crate::states::{LendAuction, LoanPool, ShardPool};
+
+/// Close a shard's empty pools and return rent to whoever funded them
+pub fn process_close_empty_pools(ctx: Context<CloseEmptyPools>, shard_id: u64) -> Result<()> {
+    let shard_pool = &ctx.accounts.shard_pool;
+    let loan_pool = &ctx.accounts.loan_pool;
+
+    require!(
+        shard_id < ctx.accounts.lend_auction.shard_count,
+        ErrorCode::InvalidShard
+    );
+    require_eq!(shard_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+    require_eq!(loan_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+    require!(
+        shard_pool.bids.is_empty() && shard_pool.asks.is_empty(),
+        ErrorCode::PoolNotEmpty
+    );
+    require_eq!(loan_pool.active_loans, 0, ErrorCode::PoolNotEmpty);
+
+    emit!(PoolsClosed {
+        shard_id,
+        shard_pool_payer: shard_pool.payer,
+        loan_pool_payer: loan_pool.payer,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(shard_id: u64)]
+pub struct CloseEmptyPools<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(
+        mut,
+        close = shard_pool_payer,
+        seeds = [b"shard_pool", shard_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = shard_pool.payer == shard_pool_payer.key() @ ErrorCode::Unauthorized
+    )]
+    pub shard_pool: Account<'info, ShardPool>,
+    #[account(
+        mut,
+        close = loan_pool_payer,
+        seeds = [b"loan_pool", shard_id.to_le_bytes().as_ref()],
+        bump,
+        constraint = loan_pool.payer == loan_pool_payer.key() @ ErrorCode::Unauthorized
+    )]
+    pub loan_pool: Account<'info, LoanPool>,
+    /// CHECK: Rent recipient recorded on the shard pool at creation
+    #[account(mut)]
+    pub shard_pool_payer: UncheckedAccount<'info>,
+    /// CHECK: Rent recipient recorded on the loan pool at creation
+    #[account(mut)]
+    pub loan_pool_payer: UncheckedAccount<'info>,
+}
@@ -0,0 +1,67 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+use crate::errors::ErrorCode;
+use crate::events::CollateralConfigured;
+use crate::states::{CollateralConfig, LendAuction};
+
+/// Admin creates or updates a collateral mint's haircut, applied when valuing baskets, and its
+/// order collateral limits in base units of the mint
+pub fn process_configure_collateral(
+    ctx: Context<ConfigureCollateral>,
+    haircut_bps: u16,
+    min_collateral: u64,
+    max_collateral: u64,
+) -> Result<()> {
+    let lend_auction = &ctx.accounts.lend_auction;
+    let mint = ctx.accounts.mint.key();
+
+    require_eq!(
+        lend_auction.admin,
+        *ctx.accounts.admin.key,
+        ErrorCode::Unauthorized
+    );
+    require!(
+        lend_auction.supported_tokens.contains(&mint),
+        ErrorCode::UnsupportedCollateral
+    );
+    require!(haircut_bps < 10_000, ErrorCode::InvalidCollateralConfig);
+    require!(
+        min_collateral > 0 && min_collateral <= max_collateral,
+        ErrorCode::InvalidCollateralConfig
+    );
+
+    let collateral_config = &mut ctx.accounts.collateral_config;
+    collateral_config.mint = mint;
+    collateral_config.haircut_bps = haircut_bps;
+    collateral_config.decimals = ctx.accounts.mint.decimals;
+    collateral_config.min_collateral = min_collateral;
+    collateral_config.max_collateral = max_collateral;
+
+    emit!(CollateralConfigured {
+        admin: lend_auction.admin,
+        mint,
+        haircut_bps,
+        min_collateral,
+        max_collateral,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ConfigureCollateral<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + 32 + 2 + 1 + 8 + 8,
+        seeds = [b"collateral_config", mint.key().as_ref()],
+        bump
+    )]
+    pub collateral_config: Account<'info, CollateralConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,78 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+use crate::errors::ErrorCode;
+use crate::events::MarketConfigured;
+use crate::states::{LendAuction, Market};
+
+/// Admin creates or updates a market's order size limits, default grace period, late penalty rate
+/// and initial collateral ratio.
+/// Amount limits are in base units of the token mint; collateral limits are set per collateral mint.
+pub fn process_configure_market(
+    ctx: Context<ConfigureMarket>,
+    min_amount: u64,
+    max_amount: u64,
+    grace_period: i64,
+    penalty_rate: u8,
+    initial_collateral_ratio: u16,
+) -> Result<()> {
+    let lend_auction = &ctx.accounts.lend_auction;
+    let token_mint = &ctx.accounts.token_mint;
+
+    require_eq!(
+        lend_auction.admin,
+        *ctx.accounts.admin.key,
+        ErrorCode::Unauthorized
+    );
+    require!(
+        lend_auction.supported_tokens.contains(&token_mint.key()),
+        ErrorCode::UnsupportedToken
+    );
+    require!(
+        min_amount > 0 && min_amount <= max_amount,
+        ErrorCode::InvalidMarketConfig
+    );
+    require!(grace_period >= 0, ErrorCode::InvalidMarketConfig);
+    // Must sit above the 120% liquidation threshold
+    require!(
+        initial_collateral_ratio > 120,
+        ErrorCode::InvalidMarketConfig
+    );
+
+    let market = &mut ctx.accounts.market;
+    market.token_mint = token_mint.key();
+    market.min_amount = min_amount;
+    market.max_amount = max_amount;
+    market.grace_period = grace_period;
+    market.penalty_rate = penalty_rate;
+    market.initial_collateral_ratio = initial_collateral_ratio;
+
+    emit!(MarketConfigured {
+        admin: lend_auction.admin,
+        token_mint: market.token_mint,
+        min_amount,
+        max_amount,
+        grace_period,
+        penalty_rate,
+        initial_collateral_ratio,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ConfigureMarket<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = 8 + 32 + 8 + 8 + 8 + 1 + 2,
+        seeds = [b"market", token_mint.key().as_ref()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,50 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+use crate::errors::ErrorCode;
+use crate::events::FeeVaultCreated;
+use crate::states::LendAuction;
+
+/// Admin creates the fee vault that collects cleanup fees for a supported mint.
+/// Admin fees are withdrawn only from this vault.
+pub fn process_create_fee_vault(ctx: Context<CreateFeeVault>) -> Result<()> {
+    let lend_auction = &ctx.accounts.lend_auction;
+    let mint = ctx.accounts.mint.key();
+
+    require_eq!(
+        lend_auction.admin,
+        *ctx.accounts.admin.key,
+        ErrorCode::Unauthorized
+    );
+    require!(
+        lend_auction.supported_tokens.contains(&mint),
+        ErrorCode::UnsupportedToken
+    );
+
+    emit!(FeeVaultCreated {
+        admin: lend_auction.admin,
+        mint,
+        fee_vault: ctx.accounts.fee_vault.key(),
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CreateFeeVault<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"fee_vault", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = lend_auction
+    )]
+    pub fee_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,83 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer, Mint, Token, TokenAccount, Transfer};
+
+use crate::errors::ErrorCode;
+use crate::events::BalanceDeposited;
+use crate::states::{LendAuction, MakerBalance};
+
+/// Deposit tokens into a maker balance that funds signed off-chain orders
+pub fn process_deposit_balance(ctx: Context<DepositBalance>, amount: u64) -> Result<()> {
+    let lend_auction = &ctx.accounts.lend_auction;
+    let owner = &ctx.accounts.owner;
+    let mint = ctx.accounts.mint.key();
+
+    require!(amount > 0, ErrorCode::InvalidAmount);
+    require!(
+        lend_auction.supported_tokens.contains(&mint),
+        ErrorCode::UnsupportedToken
+    );
+    require_eq!(
+        ctx.accounts.owner_token_account.mint,
+        mint,
+        ErrorCode::InvalidTokenAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_account.mint,
+        mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.owner_token_account.to_account_info(),
+                to: ctx.accounts.vault_account.to_account_info(),
+                authority: owner.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+
+    let maker_balance = &mut ctx.accounts.maker_balance;
+    maker_balance.owner = owner.key();
+    maker_balance.mint = mint;
+    maker_balance.amount = maker_balance
+        .amount
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    emit!(BalanceDeposited {
+        owner: owner.key(),
+        mint,
+        amount,
+        balance: maker_balance.amount,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct DepositBalance<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + 32 + 32 + 8 + 8,
+        seeds = [b"maker_balance", owner.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub maker_balance: Account<'info, MakerBalance>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(mut, constraint = owner_token_account.owner == owner.key())]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_account.owner == lend_auction.key()
+    )]
+    pub vault_account: Account<'info, TokenAccount>,
+    pub mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
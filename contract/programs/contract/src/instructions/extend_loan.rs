@@ -0,0 +1,82 @@
+use std::cmp;
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::TokenAccount;
+
+use crate::{
+    errors::ErrorCode,
+    events::LoanExtended,
+    states::{Loan, LoanStatus, Market},
+    utils::{compute_penalty, compute_repayment, lender_side_owner},
+};
+
+/// Extend a loan to a new maturity and rate, co-signed by its lender and borrower.
+/// Interest and any late penalty are checkpointed apart at the old terms first.
+/// A defaulted loan that both parties agree to extend becomes active again.
+pub fn process_extend_loan(
+    ctx: Context<ExtendLoan>,
+    loan_id: u64,
+    new_maturity_ts: i64,
+    new_rate: u8,
+) -> Result<()> {
+    let loan = &mut ctx.accounts.loan;
+
+    require!(loan.is_open(), ErrorCode::InvalidLoanStatus);
+    require_keys_eq!(
+        lender_side_owner(loan, ctx.accounts.position_token_account.as_deref())?,
+        ctx.accounts.lender.key(),
+        ErrorCode::Unauthorized
+    );
+    require_keys_eq!(
+        loan.borrower,
+        ctx.accounts.borrower.key(),
+        ErrorCode::Unauthorized
+    );
+
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        new_maturity_ts > loan.maturity_ts && new_maturity_ts > now,
+        ErrorCode::InvalidDuration
+    );
+
+    // Checkpoint what is owed under the old terms. A partial repayment may already have
+    // accrued interest past `now` up to the minimum-interest horizon, which is not owed again.
+    let accrued_interest = compute_repayment(loan, now)?
+        .checked_sub(loan.amount)
+        .ok_or(ErrorCode::Overflow)?;
+    let accrued_penalty = compute_penalty(loan, ctx.accounts.market.penalty_rate, now)?;
+
+    let old_maturity_ts = loan.maturity_ts;
+    let old_rate = loan.rate;
+    loan.accrued_interest = accrued_interest;
+    loan.accrued_penalty = accrued_penalty;
+    loan.last_accrual_ts = cmp::max(now, loan.last_accrual_ts);
+    loan.maturity_ts = new_maturity_ts;
+    loan.rate = new_rate;
+    loan.status = LoanStatus::Active;
+
+    emit!(LoanExtended {
+        loan_id,
+        lender: loan.lender,
+        borrower: loan.borrower,
+        old_maturity_ts,
+        new_maturity_ts,
+        old_rate,
+        new_rate,
+        accrued_interest,
+        accrued_penalty,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct ExtendLoan<'info> {
+    #[account(mut, seeds = [b"loan", loan_id.to_le_bytes().as_ref()], bump)]
+    pub loan: Account<'info, Loan>,
+    #[account(seeds = [b"market", loan.token_mint.as_ref()], bump)]
+    pub market: Account<'info, Market>,
+    pub lender: Signer<'info>,
+    pub position_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    pub borrower: Signer<'info>,
+}
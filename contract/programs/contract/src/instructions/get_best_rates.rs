@@ -0,0 +1,71 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::ErrorCode;
+use crate::states::{BestRates, LendAuction, ShardPool};
+
+/// Return the best bid and ask rates resting in a shard for a token mint
+pub fn process_get_best_rates(
+    ctx: Context<GetBestRates>,
+    shard_id: u64,
+    token_mint: Pubkey,
+) -> Result<BestRates> {
+    let shard_pool = &ctx.accounts.shard_pool;
+
+    require!(
+        shard_id < ctx.accounts.lend_auction.shard_count,
+        ErrorCode::InvalidShard
+    );
+    require_eq!(shard_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+
+    // Bids are sorted ascending by min_rate, asks descending by max_rate
+    let best_bid_rate = shard_pool
+        .bids
+        .iter()
+        .find(|bid| bid.token_mint == token_mint)
+        .map(|bid| bid.min_rate);
+    let best_ask_rate = shard_pool
+        .asks
+        .iter()
+        .find(|ask| ask.token_mint == token_mint)
+        .map(|ask| ask.max_rate);
+
+    let mut best_bid_amount: u64 = 0;
+    for bid in shard_pool
+        .bids
+        .iter()
+        .filter(|bid| bid.token_mint == token_mint && Some(bid.min_rate) == best_bid_rate)
+    {
+        best_bid_amount = best_bid_amount
+            .checked_add(bid.amount)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+
+    let mut best_ask_amount: u64 = 0;
+    for ask in shard_pool
+        .asks
+        .iter()
+        .filter(|ask| ask.token_mint == token_mint && Some(ask.max_rate) == best_ask_rate)
+    {
+        best_ask_amount = best_ask_amount
+            .checked_add(ask.amount)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+
+    Ok(BestRates {
+        shard_id,
+        token_mint,
+        best_bid_rate,
+        best_bid_amount,
+        best_ask_rate,
+        best_ask_amount,
+    })
+}
+
+#[derive(Accounts)]
+#[instruction(shard_id: u64)]
+pub struct GetBestRates<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(seeds = [b"shard_pool", shard_id.to_le_bytes().as_ref()], bump)]
+    pub shard_pool: Account<'info, ShardPool>,
+}
@@ -0,0 +1,55 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::ErrorCode;
+use crate::states::{Depth, LendAuction, ShardPool};
+use crate::utils::aggregate_depth;
+
+/// Return aggregated order book depth of a shard for a token mint
+pub fn process_get_depth(
+    ctx: Context<GetDepth>,
+    shard_id: u64,
+    token_mint: Pubkey,
+    levels: u8,
+) -> Result<Depth> {
+    let shard_pool = &ctx.accounts.shard_pool;
+
+    require!(
+        shard_id < ctx.accounts.lend_auction.shard_count,
+        ErrorCode::InvalidShard
+    );
+    require_eq!(shard_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+    require!(levels > 0, ErrorCode::InvalidAmount);
+
+    let bids = aggregate_depth(
+        shard_pool
+            .bids
+            .iter()
+            .filter(|bid| bid.token_mint == token_mint)
+            .map(|bid| (bid.min_rate, bid.amount)),
+        levels as usize,
+    )?;
+    let asks = aggregate_depth(
+        shard_pool
+            .asks
+            .iter()
+            .filter(|ask| ask.token_mint == token_mint)
+            .map(|ask| (ask.max_rate, ask.amount)),
+        levels as usize,
+    )?;
+
+    Ok(Depth {
+        shard_id,
+        token_mint,
+        bids,
+        asks,
+    })
+}
+
+#[derive(Accounts)]
+#[instruction(shard_id: u64)]
+pub struct GetDepth<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(seeds = [b"shard_pool", shard_id.to_le_bytes().as_ref()], bump)]
+    pub shard_pool: Account<'info, ShardPool>,
+}
@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+
+use crate::states::{Loan, LoanInfo, Market};
+use crate::utils::{
+    collateral_value, compute_health_factor, compute_penalty, compute_repayment_due,
+};
+
+/// Return a loan with its current repayment amount, late penalty and health factor.
+/// Remaining accounts hold the collateral configs of the loan's mints when it has a basket.
+pub fn process_get_loan<'info>(
+    ctx: Context<'_, '_, 'info, 'info, GetLoan<'info>>,
+    _loan_id: u64,
+) -> Result<LoanInfo> {
+    let loan = &ctx.accounts.loan;
+
+    let now = Clock::get()?.unix_timestamp;
+
+    // Settled loans only await the lender's claim
+    let (repayment_due, penalty_due, health_factor) = if loan.is_open() {
+        let repayment_due = compute_repayment_due(loan, now)?;
+        (
+            repayment_due,
+            compute_penalty(loan, ctx.accounts.market.penalty_rate, now)?,
+            compute_health_factor(
+                collateral_value(
+                    ctx.remaining_accounts,
+                    &loan.collateral_mint,
+                    loan.collateral,
+                    &loan.basket,
+                )?,
+                repayment_due,
+            )?,
+        )
+    } else {
+        (0, 0, 0)
+    };
+
+    Ok(LoanInfo {
+        loan: loan.clone().into_inner(),
+        repayment_due,
+        penalty_due,
+        health_factor,
+    })
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct GetLoan<'info> {
+    #[account(seeds = [b"loan", loan_id.to_le_bytes().as_ref()], bump)]
+    pub loan: Account<'info, Loan>,
+    #[account(seeds = [b"market", loan.token_mint.as_ref()], bump)]
+    pub market: Account<'info, Market>,
+}
@@ -1,40 +1,46 @@
-use std::str::FromStr;
+use std::cmp;
 
 use anchor_lang::prelude::*;
+use anchor_spl::associated_token::get_associated_token_address;
 use anchor_spl::token::{self, Token, TokenAccount, Transfer};
 
-use crate::utils::create_raydium_swap_instruction;
+use crate::utils::{
+    close_loan_position, collateral_value, compute_amount_due, compute_health_factor,
+    create_raydium_swap_instruction, reduce_loan_position, release_collateral,
+    repayment_accrual_ts, transfer_basket,
+};
 use crate::{
     errors::ErrorCode,
     events::LoanLiquidated,
-    states::{LendAuction, LoanPool},
+    states::{LendAuction, Loan, LoanStatus, Market, UserPositions},
     RAYDIUM_AMM_PROGRAM,
 };
 
-/// Liquidate an unhealthy loan by swapping collateral on Raydium
-pub fn process_liquidate(
-    ctx: Context<Liquidate>,
-    loan_idx: u64,
+/// Liquidate an unhealthy or defaulted loan by swapping one collateral component on Raydium:
+/// the primary collateral when `component` is `None`, otherwise that basket entry.
+/// Proceeds up to the amount owed are held in the vault until the lender side claims them and
+/// the liquidator keeps any surplus. Once the debt is covered, unsold collateral goes back to
+/// the borrower; if the collateral runs out first, the unpaid balance is written off.
+/// Remaining accounts hold the collateral configs of the loan's mints and, when the debt is
+/// covered, the associated token accounts of the unsold basket.
+pub fn process_liquidate<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Liquidate<'info>>,
+    loan_id: u64,
+    component: Option<u8>,
     minimum_amount_out: u64,
 ) -> Result<()> {
-    let loan_pool = &mut ctx.accounts.loan_pool;
+    let loan = &mut ctx.accounts.loan;
     let lend_auction = &ctx.accounts.lend_auction;
 
-    require!(
-        loan_idx < loan_pool.loans.len() as u64,
-        ErrorCode::InvalidLoanIndex
-    );
-    let loan = &mut loan_pool.loans[loan_idx as usize];
-
-    require!(!loan.repaid, ErrorCode::AlreadyRepaid);
+    require!(loan.is_open(), ErrorCode::InvalidLoanStatus);
     require!(
         lend_auction.supported_tokens.contains(&loan.token_mint),
         ErrorCode::UnsupportedToken
     );
     require_eq!(
-        ctx.accounts.lender_token_account.mint,
+        ctx.accounts.vault_token_account.mint,
         loan.token_mint,
-        ErrorCode::InvalidTokenAccount
+        ErrorCode::InvalidVaultAccount
     );
     require_eq!(
         ctx.accounts.vault_collateral_account.mint,
@@ -47,32 +53,53 @@ pub fn process_liquidate(
         ErrorCode::InvalidTokenAccount
     );
 
-    let current_slot = Clock::get()?.slot;
-    let elapsed_slots = current_slot.saturating_sub(loan.start_slot);
-    let interest_factor = (elapsed_slots as u128)
-        .checked_mul(loan.rate as u128)
-        .ok_or(ErrorCode::Overflow)?
-        .checked_div(
-            (loan.duration_slots as u128)
-                .checked_mul(100)
-                .ok_or(ErrorCode::Overflow)?,
-        )
-        .ok_or(ErrorCode::Overflow)?;
-    let repayment = loan
-        .amount
-        .checked_add(
-            (loan.amount as u128)
-                .checked_mul(interest_factor)
-                .ok_or(ErrorCode::Overflow)? as u64,
-        )
-        .ok_or(ErrorCode::Overflow)?;
+    // The debt covers repayment and any late penalty
+    let now = Clock::get()?.unix_timestamp;
+    let (repayment, penalty) = compute_amount_due(loan, ctx.accounts.market.penalty_rate, now)?;
+    let total_due = repayment.checked_add(penalty).ok_or(ErrorCode::Overflow)?;
 
-    let health_factor = (loan.collateral as u128)
-        .checked_mul(100)
-        .ok_or(ErrorCode::Overflow)?
-        .checked_div(repayment as u128)
-        .ok_or(ErrorCode::Overflow)?;
-    require!(health_factor <= 120, ErrorCode::LoanNotUnhealthy);
+    // Defaulted loans may be liquidated regardless of health
+    if loan.status != LoanStatus::Defaulted {
+        let value = collateral_value(
+            ctx.remaining_accounts,
+            &loan.collateral_mint,
+            loan.collateral,
+            &loan.basket,
+        )?;
+        let health_factor = compute_health_factor(value, total_due)?;
+        require!(health_factor <= 120, ErrorCode::LoanNotUnhealthy);
+    }
+
+    // Pick the component to sell and the vault account holding it
+    let (collateral_mint, collateral_sold, source) = match component {
+        None => (
+            loan.collateral_mint,
+            loan.collateral,
+            ctx.accounts.vault_collateral_account.to_account_info(),
+        ),
+        Some(index) => {
+            let basket_component = *loan
+                .basket
+                .get(index as usize)
+                .ok_or(ErrorCode::InvalidBasket)?;
+            let vault_component_account = ctx
+                .accounts
+                .vault_component_account
+                .as_ref()
+                .ok_or(ErrorCode::InvalidBasketAccount)?;
+            require_keys_eq!(
+                vault_component_account.key(),
+                get_associated_token_address(&lend_auction.key(), &basket_component.mint),
+                ErrorCode::InvalidBasketAccount
+            );
+            (
+                basket_component.mint,
+                basket_component.amount,
+                vault_component_account.to_account_info(),
+            )
+        }
+    };
+    require!(collateral_sold > 0, ErrorCode::InvalidCollateral);
 
     // Perform Raydium swap: collateral -> loan token
     let swap_instruction = create_raydium_swap_instruction(
@@ -83,20 +110,21 @@ pub fn process_liquidate(
         &ctx.accounts.amm_coin_vault,
         &ctx.accounts.amm_pc_vault,
         &ctx.accounts.market_program,
-        &ctx.accounts.market,
+        &ctx.accounts.serum_market,
         &ctx.accounts.market_bids,
         &ctx.accounts.market_asks,
         &ctx.accounts.market_event_queue,
         &ctx.accounts.market_coin_vault,
         &ctx.accounts.market_pc_vault,
         &ctx.accounts.market_vault_signer,
-        &ctx.accounts.vault_collateral_account.to_account_info(), // Source: collateral
+        &source,                                                  // Source: collateral
         &ctx.accounts.liquidator_token_account.to_account_info(), // Destination: loan token
         &lend_auction.to_account_info(),                          // Authority
-        loan.collateral,
+        collateral_sold,
         minimum_amount_out,
     )?;
 
+    let balance_before = ctx.accounts.liquidator_token_account.amount;
     anchor_lang::solana_program::program::invoke_signed(
         &swap_instruction,
         &[
@@ -107,14 +135,14 @@ pub fn process_liquidate(
             ctx.accounts.amm_coin_vault.to_account_info(),
             ctx.accounts.amm_pc_vault.to_account_info(),
             ctx.accounts.market_program.to_account_info(),
-            ctx.accounts.market.to_account_info(),
+            ctx.accounts.serum_market.to_account_info(),
             ctx.accounts.market_bids.to_account_info(),
             ctx.accounts.market_asks.to_account_info(),
             ctx.accounts.market_event_queue.to_account_info(),
             ctx.accounts.market_coin_vault.to_account_info(),
             ctx.accounts.market_pc_vault.to_account_info(),
             ctx.accounts.market_vault_signer.to_account_info(),
-            ctx.accounts.vault_collateral_account.to_account_info(),
+            source.clone(),
             ctx.accounts.liquidator_token_account.to_account_info(),
             ctx.accounts.lend_auction.to_account_info(),
             ctx.accounts.token_program.to_account_info(),
@@ -123,61 +151,190 @@ pub fn process_liquidate(
     )?;
 
     // Distribute proceeds
-    let proceeds = ctx.accounts.liquidator_token_account.amount;
-    require_gte!(proceeds, repayment, ErrorCode::InsufficientSwapProceeds);
+    ctx.accounts.liquidator_token_account.reload()?;
+    let proceeds = ctx
+        .accounts
+        .liquidator_token_account
+        .amount
+        .checked_sub(balance_before)
+        .ok_or(ErrorCode::InsufficientSwapProceeds)?;
+    require!(proceeds > 0, ErrorCode::InsufficientSwapProceeds);
+    let paid = cmp::min(proceeds, total_due);
 
-    // Transfer repayment (capital + interest) to lender
+    // Hold repayment (capital + interest + penalty) in the vault for the lender to claim
     token::transfer(
         CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
             Transfer {
                 from: ctx.accounts.liquidator_token_account.to_account_info(),
-                to: ctx.accounts.lender_token_account.to_account_info(),
+                to: ctx.accounts.vault_token_account.to_account_info(),
                 authority: ctx.accounts.liquidator.to_account_info(),
             },
         ),
-        repayment,
+        paid,
     )?;
+    loan.claimable = loan
+        .claimable
+        .checked_add(paid)
+        .ok_or(ErrorCode::Overflow)?;
+
+    let liquidator_profit = proceeds - paid;
+    let settled = paid == total_due;
+
+    if settled {
+        close_loan_position(&mut ctx.accounts.lender_positions, loan)?;
+        close_loan_position(&mut ctx.accounts.borrower_positions, loan)?;
+    } else {
+        // Checkpoint the partial payment, penalty and interest first, then principal
+        let interest_due = repayment - loan.amount;
+        let penalty_paid = cmp::min(paid, penalty);
+        let interest_paid = cmp::min(paid - penalty_paid, interest_due);
+        let principal_paid = paid - penalty_paid - interest_paid;
+        let primary_sold = if component.is_none() {
+            collateral_sold
+        } else {
+            0
+        };
+        reduce_loan_position(
+            &mut ctx.accounts.lender_positions,
+            loan,
+            principal_paid,
+            primary_sold,
+        )?;
+        reduce_loan_position(
+            &mut ctx.accounts.borrower_positions,
+            loan,
+            principal_paid,
+            primary_sold,
+        )?;
+        loan.accrued_interest = interest_due - interest_paid;
+        loan.accrued_penalty = penalty - penalty_paid;
+        loan.last_accrual_ts = repayment_accrual_ts(loan, now)?;
+        loan.amount -= principal_paid;
+    }
+
+    // Drop the sold component from the loan
+    match component {
+        None => loan.collateral = 0,
+        Some(index) => {
+            let sold = loan.basket.remove(index as usize);
+            if !settled {
+                release_collateral(
+                    &mut ctx.accounts.borrower_positions,
+                    &sold.mint,
+                    sold.amount,
+                )?;
+            }
+        }
+    }
 
-    let liquidator_profit = proceeds.checked_sub(repayment).ok_or(ErrorCode::Overflow)?;
+    // Nothing left to sell: the unpaid balance is written off, and the loan closes once the
+    // lender side claims the proceeds
+    let mut shortfall = 0;
+    if !settled && loan.collateral == 0 && loan.basket.is_empty() {
+        shortfall = loan
+            .amount
+            .checked_add(loan.accrued_interest)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_add(loan.accrued_penalty)
+            .ok_or(ErrorCode::Overflow)?;
+        close_loan_position(&mut ctx.accounts.lender_positions, loan)?;
+        close_loan_position(&mut ctx.accounts.borrower_positions, loan)?;
+        loan.status = LoanStatus::Liquidated;
+    }
+
+    if settled {
+        // Return unsold collateral to the borrower
+        let signer_seeds: &[&[&[u8]]] = &[&[b"lend_auction", &[ctx.bumps.lend_auction]]];
+        if loan.collateral > 0 {
+            let borrower_collateral_account = ctx
+                .accounts
+                .borrower_collateral_account
+                .as_ref()
+                .ok_or(ErrorCode::InvalidTokenAccount)?;
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.vault_collateral_account.to_account_info(),
+                        to: borrower_collateral_account.to_account_info(),
+                        authority: lend_auction.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                loan.collateral,
+            )?;
+        }
+        transfer_basket(
+            ctx.remaining_accounts,
+            &loan.basket,
+            &lend_auction.key(),
+            &loan.borrower,
+            &lend_auction.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            signer_seeds,
+        )?;
+        loan.status = LoanStatus::Liquidated;
+    }
 
-    loan.repaid = true;
     emit!(LoanLiquidated {
+        loan_id,
         lender: loan.lender,
         borrower: loan.borrower,
         liquidator: ctx.accounts.liquidator.key(),
-        amount: repayment,
-        collateral: loan.collateral,
+        amount: paid,
+        collateral: collateral_sold,
         profit: liquidator_profit,
+        settled,
+        shortfall,
         shard_id: loan.shard_id,
         token_mint: loan.token_mint,
-        collateral_mint: loan.collateral_mint,
+        collateral_mint,
     });
 
     Ok(())
 }
 #[derive(Accounts)]
-#[instruction(loan_idx: u64, minimum_amount_out: u64)]
+#[instruction(loan_id: u64)]
 pub struct Liquidate<'info> {
     #[account(mut, seeds = [b"lend_auction"], bump)]
     pub lend_auction: Account<'info, LendAuction>,
-    #[account(mut, seeds = [b"loan_pool", loan_pool.shard_id.to_le_bytes().as_ref()], bump)]
-    pub loan_pool: Account<'info, LoanPool>,
+    #[account(
+        mut,
+        seeds = [b"loan", loan_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub loan: Account<'info, Loan>,
+    #[account(seeds = [b"market", loan.token_mint.as_ref()], bump)]
+    pub market: Box<Account<'info, Market>>,
+    #[account(mut, seeds = [b"user_positions", loan.lender.as_ref()], bump)]
+    pub lender_positions: Box<Account<'info, UserPositions>>,
+    #[account(mut, seeds = [b"user_positions", loan.borrower.as_ref()], bump)]
+    pub borrower_positions: Box<Account<'info, UserPositions>>,
     #[account(mut)]
     pub liquidator: Signer<'info>,
     #[account(mut, constraint = liquidator_token_account.owner == liquidator.key())]
     pub liquidator_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub lender_token_account: Account<'info, TokenAccount>,
     #[account(
         mut,
-        seeds = [b"vault_collateral", loan_pool.shard_id.to_le_bytes().as_ref()],
-        bump,
+        constraint = vault_token_account.owner == lend_auction.key()
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
         constraint = vault_collateral_account.owner == lend_auction.key()
     )]
     pub vault_collateral_account: Account<'info, TokenAccount>,
+    /// Lend auction's associated token account for the basket component being sold
+    #[account(
+        mut,
+        constraint = vault_component_account.owner == lend_auction.key()
+    )]
+    pub vault_component_account: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, constraint = borrower_collateral_account.owner == loan.borrower)]
+    pub borrower_collateral_account: Option<Box<Account<'info, TokenAccount>>>,
     /// CHECK: Raydium AMM Program
-    #[account(constraint = raydium_amm_program.key() == Pubkey::from_str(RAYDIUM_AMM_PROGRAM).unwrap())]
+    #[account(constraint = raydium_amm_program.key() == RAYDIUM_AMM_PROGRAM)]
     pub raydium_amm_program: UncheckedAccount<'info>,
     /// CHECK: AMM account
     #[account(mut)]
@@ -195,9 +352,9 @@ pub struct Liquidate<'info> {
     pub amm_pc_vault: UncheckedAccount<'info>,
     /// CHECK: Market program
     pub market_program: UncheckedAccount<'info>,
-    /// CHECK: Market account
+    /// CHECK: Serum market account
     #[account(mut)]
-    pub market: UncheckedAccount<'info>,
+    pub serum_market: UncheckedAccount<'info>,
     /// CHECK: Market bids
     #[account(mut)]
     pub market_bids: UncheckedAccount<'info>,
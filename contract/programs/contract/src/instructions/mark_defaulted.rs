@@ -0,0 +1,47 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::ErrorCode;
+use crate::events::LoanDefaulted;
+use crate::states::{Loan, LoanStatus, Market};
+
+/// Permissionlessly mark an unpaid loan defaulted once maturity plus the market grace period has passed
+pub fn process_mark_defaulted(ctx: Context<MarkDefaulted>, loan_id: u64) -> Result<()> {
+    let loan = &mut ctx.accounts.loan;
+
+    require!(
+        loan.status == LoanStatus::Active,
+        ErrorCode::InvalidLoanStatus
+    );
+    let default_ts = loan
+        .maturity_ts
+        .checked_add(ctx.accounts.market.grace_period)
+        .ok_or(ErrorCode::Overflow)?;
+    require!(
+        Clock::get()?.unix_timestamp > default_ts,
+        ErrorCode::GracePeriodActive
+    );
+
+    loan.status = LoanStatus::Defaulted;
+
+    emit!(LoanDefaulted {
+        loan_id,
+        lender: loan.lender,
+        borrower: loan.borrower,
+        amount: loan.amount,
+        collateral: loan.collateral,
+        maturity_ts: loan.maturity_ts,
+        shard_id: loan.shard_id,
+        token_mint: loan.token_mint,
+        collateral_mint: loan.collateral_mint,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct MarkDefaulted<'info> {
+    #[account(mut, seeds = [b"loan", loan_id.to_le_bytes().as_ref()], bump)]
+    pub loan: Account<'info, Loan>,
+    #[account(seeds = [b"market", loan.token_mint.as_ref()], bump)]
+    pub market: Account<'info, Market>,
+}
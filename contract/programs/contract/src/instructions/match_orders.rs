@@ -0,0 +1,290 @@
+use std::cmp;
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+use crate::{
+    errors::ErrorCode,
+    events::{LoanIssued, OrdersMatched},
+    math::compute_maturity,
+    states::{LendAuction, Loan, LoanPool, LoanStatus, Market, ShardPool},
+    utils::{
+        collateral_value, create_loan_account, find_crossing_pair, fit_user_positions,
+        load_collateral_config, load_user_positions, open_loan_position, proceeds_destination,
+        required_collateral, split_basket, split_crank_reward, sync_order_shards,
+        validate_collateral_size,
+    },
+};
+
+/// Permissionlessly match crossed resting bids and asks within a shard.
+/// Remaining accounts hold, per match in match order, an uninitialized loan account
+/// followed by the borrower's proceeds account, then the position accounts of every matched
+/// lender and borrower and the collateral configs of the collateral mints of asks with a basket or left
+/// partially filled, whose remaining collateral must stay within the mint's size limits. The cranker pays loan
+/// account rent.
+/// When the cranker passes a token account, its reward is withheld from each match's loan proceeds,
+/// so matching never spends fees collected by the protocol.
+pub fn process_match_orders<'info>(
+    ctx: Context<'_, '_, 'info, 'info, MatchOrders<'info>>,
+    shard_id: u64,
+    max_matches: u8,
+) -> Result<()> {
+    let lend_auction = &mut ctx.accounts.lend_auction;
+    let shard_pool = &mut ctx.accounts.shard_pool;
+    let loan_pool = &mut ctx.accounts.loan_pool;
+    let token_mint = ctx.accounts.token_mint.key();
+
+    require!(shard_id < lend_auction.shard_count, ErrorCode::InvalidShard);
+    require_eq!(shard_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+    require_eq!(loan_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+    require!(max_matches > 0, ErrorCode::InvalidAmount);
+    require!(
+        lend_auction.supported_tokens.contains(&token_mint),
+        ErrorCode::UnsupportedToken
+    );
+    require_eq!(
+        ctx.accounts.vault_token_account.mint,
+        token_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    if let Some(cranker_token_account) = &ctx.accounts.cranker_token_account {
+        require_eq!(
+            cranker_token_account.mint,
+            token_mint,
+            ErrorCode::InvalidTokenAccount
+        );
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    let mut matches: u64 = 0;
+    let mut volume: u64 = 0;
+    let mut reward: u64 = 0;
+
+    while matches < max_matches as u64 {
+        let Some((bid_idx, ask_idx, rate)) = find_crossing_pair(
+            &shard_pool.bids,
+            &shard_pool.asks,
+            &ctx.accounts.market,
+            |ask| {
+                collateral_value(
+                    ctx.remaining_accounts,
+                    &ask.collateral_mint,
+                    ask.collateral,
+                    &ask.basket,
+                )
+            },
+            |ask, remaining| {
+                load_collateral_config(ctx.remaining_accounts, &ask.collateral_mint)
+                    .and_then(|config| validate_collateral_size(&config, remaining))
+                    .is_ok()
+            },
+        ) else {
+            break;
+        };
+
+        let bid = shard_pool.bids[bid_idx].clone();
+        let ask = shard_pool.asks[ask_idx].clone();
+        let loan_amount = cmp::min(bid.amount, ask.amount);
+        let loan_collateral = ask
+            .collateral
+            .checked_mul(loan_amount)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(ask.amount)
+            .ok_or(ErrorCode::Overflow)?;
+
+        let loan = Loan {
+            loan_id: lend_auction.total_loans,
+            payer: ctx.accounts.cranker.key(),
+            lender: bid.lender,
+            borrower: ask.borrower,
+            amount: loan_amount,
+            rate,
+            collateral: loan_collateral,
+            status: LoanStatus::Active,
+            shard_id,
+            token_mint,
+            collateral_mint: ask.collateral_mint,
+            start_ts: now,
+            maturity_ts: compute_maturity(now, bid.duration_slots)?,
+            accrued_interest: 0,
+            accrued_penalty: 0,
+            last_accrual_ts: now,
+            early_repayment: bid.early_repayment,
+            position_mint: None,
+            claimable: 0,
+            basket: split_basket(&ask.basket, loan_amount, ask.amount)?,
+        };
+
+        // Validate collateral ratio over the whole basket
+        require_gte!(
+            collateral_value(
+                ctx.remaining_accounts,
+                &loan.collateral_mint,
+                loan.collateral,
+                &loan.basket,
+            )?,
+            required_collateral(&ctx.accounts.market, loan.amount)?,
+            ErrorCode::InsufficientCollateral
+        );
+
+        // Loan proceeds go to the ask's payout account, or the borrower's associated token account
+        let loan_account = ctx
+            .remaining_accounts
+            .get(matches as usize * 2)
+            .ok_or(ErrorCode::InvalidLoanAccount)?;
+        let borrower_account_info = ctx
+            .remaining_accounts
+            .get(matches as usize * 2 + 1)
+            .ok_or(ErrorCode::MissingBorrowerAccount)?;
+        let borrower_token_account = Account::<TokenAccount>::try_from(borrower_account_info)?;
+        require_keys_eq!(
+            borrower_token_account.key(),
+            proceeds_destination(&ask.borrower, &ask.token_mint, ask.payout),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_eq!(
+            borrower_token_account.mint,
+            token_mint,
+            ErrorCode::InvalidTokenAccount
+        );
+
+        // The borrower owes the full loan amount; the crank reward comes out of its proceeds
+        let (proceeds, match_reward) = match &ctx.accounts.cranker_token_account {
+            Some(_) => split_crank_reward(loan.amount)?,
+            None => (loan.amount, 0),
+        };
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault_token_account.to_account_info(),
+                    to: borrower_account_info.clone(),
+                    authority: lend_auction.to_account_info(),
+                },
+                &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+            ),
+            proceeds,
+        )?;
+        if let Some(cranker_token_account) = &ctx.accounts.cranker_token_account {
+            if match_reward > 0 {
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: ctx.accounts.vault_token_account.to_account_info(),
+                            to: cranker_token_account.to_account_info(),
+                            authority: lend_auction.to_account_info(),
+                        },
+                        &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+                    ),
+                    match_reward,
+                )?;
+            }
+        }
+
+        // Keep unfilled remainders resting in the book
+        if bid.amount > loan_amount {
+            shard_pool.bids[bid_idx].amount = bid.amount - loan_amount;
+        } else {
+            shard_pool.bids.remove(bid_idx);
+        }
+        if ask.amount > loan_amount {
+            let remaining_ask = &mut shard_pool.asks[ask_idx];
+            remaining_ask.amount = ask.amount - loan_amount;
+            remaining_ask.collateral = ask
+                .collateral
+                .checked_sub(loan_collateral)
+                .ok_or(ErrorCode::Overflow)?;
+            for (component, taken) in remaining_ask.basket.iter_mut().zip(&loan.basket) {
+                component.amount = component
+                    .amount
+                    .checked_sub(taken.amount)
+                    .ok_or(ErrorCode::Overflow)?;
+            }
+        } else {
+            shard_pool.asks.remove(ask_idx);
+        }
+
+        create_loan_account(
+            loan_account,
+            &ctx.accounts.cranker.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &loan,
+        )?;
+        for owner in [&loan.lender, &loan.borrower] {
+            let mut positions = load_user_positions(ctx.remaining_accounts, owner)?;
+            open_loan_position(&mut positions, &loan)?;
+            sync_order_shards(&mut positions, shard_pool)?;
+            fit_user_positions(
+                &positions,
+                &ctx.accounts.cranker.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+            )?;
+            positions.exit(&crate::ID)?;
+        }
+        lend_auction.total_loans = lend_auction
+            .total_loans
+            .checked_add(1)
+            .ok_or(ErrorCode::Overflow)?;
+        loan_pool.active_loans = loan_pool
+            .active_loans
+            .checked_add(1)
+            .ok_or(ErrorCode::Overflow)?;
+
+        emit!(LoanIssued {
+            loan_id: loan.loan_id,
+            lender: loan.lender,
+            borrower: loan.borrower,
+            amount: loan.amount,
+            rate: loan.rate,
+            shard_id,
+            token_mint: loan.token_mint,
+            collateral_mint: loan.collateral_mint,
+        });
+
+        matches = matches.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        volume = volume.checked_add(loan.amount).ok_or(ErrorCode::Overflow)?;
+        reward = reward
+            .checked_add(match_reward)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+
+    require!(matches > 0, ErrorCode::NoMatchesFound);
+
+    emit!(OrdersMatched {
+        cranker: ctx.accounts.cranker.key(),
+        shard_id,
+        matches,
+        volume,
+        reward,
+        token_mint,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(shard_id: u64, max_matches: u8)]
+pub struct MatchOrders<'info> {
+    #[account(mut, seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Box<Account<'info, LendAuction>>,
+    #[account(seeds = [b"market", token_mint.key().as_ref()], bump)]
+    pub market: Box<Account<'info, Market>>,
+    #[account(mut, seeds = [b"shard_pool", shard_id.to_le_bytes().as_ref()], bump)]
+    pub shard_pool: Box<Account<'info, ShardPool>>,
+    #[account(mut, seeds = [b"loan_pool", shard_id.to_le_bytes().as_ref()], bump)]
+    pub loan_pool: Box<Account<'info, LoanPool>>,
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    #[account(mut, constraint = cranker_token_account.owner == cranker.key())]
+    pub cranker_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = vault_token_account.owner == lend_auction.key()
+    )]
+    pub vault_token_account: Box<Account<'info, TokenAccount>>,
+    pub token_mint: Box<Account<'info, Mint>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
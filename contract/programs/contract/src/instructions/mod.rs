@@ -1,20 +1,101 @@
+pub mod add_collateral;
+pub use add_collateral::*;
+
+pub mod approve_delegate;
+pub use approve_delegate::*;
+
+pub mod cancel_nonces;
+pub use cancel_nonces::*;
+
+pub mod cancel_order;
+pub use cancel_order::*;
+
+pub mod claim;
+pub use claim::*;
+
+pub mod claim_collateral;
+pub use claim_collateral::*;
+
 pub mod cleanup;
 pub use cleanup::*;
 
+pub mod close_empty_pools;
+pub use close_empty_pools::*;
+
+pub mod configure_collateral;
+pub use configure_collateral::*;
+
+pub mod configure_market;
+pub use configure_market::*;
+
+pub mod create_fee_vault;
+pub use create_fee_vault::*;
+
+pub mod deposit_balance;
+pub use deposit_balance::*;
+
+pub mod extend_loan;
+pub use extend_loan::*;
+
+pub mod get_best_rates;
+pub use get_best_rates::*;
+
+pub mod get_depth;
+pub use get_depth::*;
+
+pub mod get_loan;
+pub use get_loan::*;
+
 pub mod initialize;
 pub use initialize::*;
 
 pub mod liquidate;
 pub use liquidate::*;
 
+pub mod mark_defaulted;
+pub use mark_defaulted::*;
+
+pub mod match_orders;
+pub use match_orders::*;
+
+pub mod quote_match;
+pub use quote_match::*;
+
+pub mod revoke_delegate;
+pub use revoke_delegate::*;
+
+pub mod settle_signed_order;
+pub use settle_signed_order::*;
+
 pub mod submit_ask;
 pub use submit_ask::*;
 
 pub mod submit_bid;
 pub use submit_bid::*;
 
+pub mod refinance;
+pub use refinance::*;
+
 pub mod repay;
 pub use repay::*;
 
+pub mod repay_for;
+pub use repay_for::*;
+
+pub mod repay_partial;
+pub use repay_partial::*;
+
+pub mod repay_with_collateral;
+pub use repay_with_collateral::*;
+
+pub mod tokenize_loan;
+pub use tokenize_loan::*;
+
+pub mod withdraw_collateral;
+pub use withdraw_collateral::*;
+
 pub mod withdraw_fee;
 pub use withdraw_fee::*;
+
+pub mod withdraw_balance;
+pub use withdraw_balance::*;
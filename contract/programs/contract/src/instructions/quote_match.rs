@@ -0,0 +1,109 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::ErrorCode;
+use crate::states::{
+    Ask, Bid, EarlyRepayment, LendAuction, MatchQuote, OrderSide, SelfTradePrevention, ShardPool,
+};
+use crate::utils::{match_ask, match_bid};
+
+/// Quote how an order would match against a shard without modifying it
+pub fn process_quote_match(
+    ctx: Context<QuoteMatch>,
+    shard_id: u64,
+    side: OrderSide,
+    token_mint: Pubkey,
+    amount: u64,
+    rate: u8,
+) -> Result<MatchQuote> {
+    let shard_pool = &ctx.accounts.shard_pool;
+
+    require!(
+        shard_id < ctx.accounts.lend_auction.shard_count,
+        ErrorCode::InvalidShard
+    );
+    require_eq!(shard_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+    require!(amount > 0, ErrorCode::InvalidAmount);
+
+    let slot = Clock::get()?.slot;
+    let fills: Vec<(u64, u8)> = match side {
+        OrderSide::Bid => {
+            let bid = Bid {
+                lender: Pubkey::default(),
+                amount,
+                min_rate: rate,
+                slot,
+                token_mint,
+                duration_slots: 0,
+                early_repayment: EarlyRepayment::ProRata,
+                delegate: None,
+            };
+            match_bid(
+                &bid,
+                &mut shard_pool.asks.clone(),
+                SelfTradePrevention::Skip,
+            )?
+            .matches
+            .into_iter()
+            .map(|(ask, rate)| (ask.amount, rate))
+            .collect()
+        }
+        OrderSide::Ask => {
+            let ask = Ask {
+                borrower: Pubkey::default(),
+                amount,
+                max_rate: rate,
+                collateral: 0,
+                slot,
+                token_mint,
+                collateral_mint: Pubkey::default(),
+                payout: None,
+                basket: Vec::new(),
+                delegate: None,
+            };
+            match_ask(
+                &ask,
+                &mut shard_pool.bids.clone(),
+                SelfTradePrevention::Skip,
+            )?
+            .matches
+            .into_iter()
+            .map(|(bid, rate)| (bid.amount, rate))
+            .collect()
+        }
+    };
+
+    let mut matched_amount: u64 = 0;
+    let mut weighted_rate: u128 = 0;
+    for (fill_amount, fill_rate) in &fills {
+        let fill_amount = (*fill_amount).min(amount - matched_amount);
+        matched_amount = matched_amount
+            .checked_add(fill_amount)
+            .ok_or(ErrorCode::Overflow)?;
+        weighted_rate = weighted_rate
+            .checked_add((fill_amount as u128) * (*fill_rate as u128))
+            .ok_or(ErrorCode::Overflow)?;
+    }
+    let average_rate = if matched_amount > 0 {
+        (weighted_rate / matched_amount as u128) as u8
+    } else {
+        0
+    };
+
+    Ok(MatchQuote {
+        side,
+        requested_amount: amount,
+        matched_amount,
+        fills: fills.len() as u32,
+        average_rate,
+        fully_matched: matched_amount == amount,
+    })
+}
+
+#[derive(Accounts)]
+#[instruction(shard_id: u64)]
+pub struct QuoteMatch<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(seeds = [b"shard_pool", shard_id.to_le_bytes().as_ref()], bump)]
+    pub shard_pool: Account<'info, ShardPool>,
+}
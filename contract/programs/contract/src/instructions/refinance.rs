@@ -0,0 +1,200 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::ErrorCode,
+    events::LoanRefinanced,
+    math::compute_maturity,
+    states::{LendAuction, Loan, LoanPool, LoanStatus, Market, ShardPool, UserPositions},
+    utils::{
+        close_loan_position, collateral_value, compute_penalty, compute_repayment_due,
+        fit_user_positions, is_valid_remainder, open_loan_position, required_collateral,
+        sync_order_shards,
+    },
+};
+
+/// Borrower rolls a loan into a resting bid from the same market.
+/// The bid's funds stay in the vault as the old lender's claimable payoff, collateral stays
+/// in the vault, and a new loan for the payoff amount replaces the old one in the old loan's shard.
+/// Remaining accounts hold the collateral configs of the loan's mints when it has a basket.
+pub fn process_refinance<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Refinance<'info>>,
+    loan_id: u64,
+    shard_id: u64,
+    bid_idx: u64,
+    max_rate: u8,
+) -> Result<()> {
+    let lend_auction = &mut ctx.accounts.lend_auction;
+    let shard_pool = &mut ctx.accounts.shard_pool;
+    let old_loan = &mut ctx.accounts.loan;
+
+    require!(shard_id < lend_auction.shard_count, ErrorCode::InvalidShard);
+    require_eq!(shard_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+    require!(
+        old_loan.status == LoanStatus::Active,
+        ErrorCode::InvalidLoanStatus
+    );
+    require_keys_eq!(
+        old_loan.borrower,
+        ctx.accounts.borrower.key(),
+        ErrorCode::Unauthorized
+    );
+    require!(
+        bid_idx < shard_pool.bids.len() as u64,
+        ErrorCode::InvalidOrderIndex
+    );
+    let bid = shard_pool.bids[bid_idx as usize].clone();
+    require_keys_eq!(
+        bid.token_mint,
+        old_loan.token_mint,
+        ErrorCode::InvalidOrderIndex
+    );
+    require_keys_eq!(
+        bid.lender,
+        ctx.accounts.new_lender.key(),
+        ErrorCode::InvalidOrderIndex
+    );
+    require_keys_neq!(bid.lender, old_loan.borrower, ErrorCode::Unauthorized);
+    require_keys_neq!(bid.lender, old_loan.lender, ErrorCode::RefinanceSameLender);
+    require!(bid.min_rate <= max_rate, ErrorCode::RateTooHigh);
+
+    // Payoff follows the same terms as a full repayment
+    let now = Clock::get()?.unix_timestamp;
+    let payoff = compute_repayment_due(old_loan, now)?
+        .checked_add(compute_penalty(
+            old_loan,
+            ctx.accounts.market.penalty_rate,
+            now,
+        )?)
+        .ok_or(ErrorCode::Overflow)?;
+    require_gte!(bid.amount, payoff, ErrorCode::InsufficientFunds);
+    require_gte!(
+        collateral_value(
+            ctx.remaining_accounts,
+            &old_loan.collateral_mint,
+            old_loan.collateral,
+            &old_loan.basket
+        )?,
+        required_collateral(&ctx.accounts.market, payoff)?,
+        ErrorCode::InsufficientCollateral
+    );
+
+    // Consume the bid, keeping any valid remainder resting
+    if bid.amount > payoff {
+        require!(
+            is_valid_remainder(&ctx.accounts.market, bid.amount - payoff),
+            ErrorCode::OrderTooSmall
+        );
+        shard_pool.bids[bid_idx as usize].amount = bid.amount - payoff;
+    } else {
+        shard_pool.bids.remove(bid_idx as usize);
+    }
+
+    // The new lender's deposit is credited to the old lender without leaving the vault
+    old_loan.claimable = old_loan
+        .claimable
+        .checked_add(payoff)
+        .ok_or(ErrorCode::Overflow)?;
+    old_loan.status = LoanStatus::Repaid;
+
+    let new_loan = &mut ctx.accounts.new_loan;
+    new_loan.set_inner(Loan {
+        loan_id: lend_auction.total_loans,
+        payer: ctx.accounts.borrower.key(),
+        lender: bid.lender,
+        borrower: old_loan.borrower,
+        amount: payoff,
+        rate: bid.min_rate,
+        collateral: old_loan.collateral,
+        status: LoanStatus::Active,
+        shard_id: old_loan.shard_id,
+        token_mint: old_loan.token_mint,
+        collateral_mint: old_loan.collateral_mint,
+        start_ts: now,
+        maturity_ts: compute_maturity(now, bid.duration_slots)?,
+        accrued_interest: 0,
+        accrued_penalty: 0,
+        last_accrual_ts: now,
+        early_repayment: bid.early_repayment,
+        position_mint: None,
+        claimable: 0,
+        basket: old_loan.basket.clone(),
+    });
+    lend_auction.total_loans = lend_auction
+        .total_loans
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // The old loan stays in the pool until its lender claims the payoff
+    let loan_pool = &mut ctx.accounts.loan_pool;
+    loan_pool.active_loans = loan_pool
+        .active_loans
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+    close_loan_position(&mut ctx.accounts.lender_positions, old_loan)?;
+    close_loan_position(&mut ctx.accounts.borrower_positions, old_loan)?;
+    open_loan_position(&mut ctx.accounts.borrower_positions, new_loan)?;
+    let new_lender_positions = &mut ctx.accounts.new_lender_positions;
+    open_loan_position(new_lender_positions, new_loan)?;
+    sync_order_shards(new_lender_positions, shard_pool)?;
+    for positions in [
+        &ctx.accounts.borrower_positions,
+        &ctx.accounts.new_lender_positions,
+    ] {
+        fit_user_positions(
+            positions,
+            &ctx.accounts.borrower.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+    }
+
+    emit!(LoanRefinanced {
+        old_loan_id: loan_id,
+        new_loan_id: new_loan.loan_id,
+        borrower: new_loan.borrower,
+        old_lender: old_loan.lender,
+        new_lender: new_loan.lender,
+        payoff,
+        rate: new_loan.rate,
+        shard_id: new_loan.shard_id,
+        token_mint: new_loan.token_mint,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64, shard_id: u64)]
+pub struct Refinance<'info> {
+    #[account(mut, seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Box<Account<'info, LendAuction>>,
+    #[account(seeds = [b"market", loan.token_mint.as_ref()], bump)]
+    pub market: Box<Account<'info, Market>>,
+    #[account(
+        mut,
+        seeds = [b"loan", loan_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub loan: Box<Account<'info, Loan>>,
+    #[account(mut, seeds = [b"loan_pool", loan.shard_id.to_le_bytes().as_ref()], bump)]
+    pub loan_pool: Box<Account<'info, LoanPool>>,
+    #[account(
+        init,
+        payer = borrower,
+        space = Loan::SPACE,
+        seeds = [b"loan", lend_auction.total_loans.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub new_loan: Box<Account<'info, Loan>>,
+    #[account(mut, seeds = [b"shard_pool", shard_id.to_le_bytes().as_ref()], bump)]
+    pub shard_pool: Box<Account<'info, ShardPool>>,
+    #[account(mut)]
+    pub borrower: Signer<'info>,
+    #[account(mut, seeds = [b"user_positions", loan.borrower.as_ref()], bump)]
+    pub borrower_positions: Box<Account<'info, UserPositions>>,
+    #[account(mut, seeds = [b"user_positions", loan.lender.as_ref()], bump)]
+    pub lender_positions: Box<Account<'info, UserPositions>>,
+    /// CHECK: Owner of the refinancing bid, validated against the bid
+    pub new_lender: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"user_positions", new_lender.key().as_ref()], bump)]
+    pub new_lender_positions: Box<Account<'info, UserPositions>>,
+    pub system_program: Program<'info, System>,
+}
@@ -1,25 +1,24 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{transfer, Mint, Token, TokenAccount, Transfer};
+use anchor_spl::token::{Token, TokenAccount};
 
 use crate::{
     errors::ErrorCode,
     events::LoanRepaid,
-    states::{LendAuction, LoanPool}, utils::compute_shard_id,
+    states::{Delegate, LendAuction, Loan, Market, UserPositions, DELEGATE_REPAY},
+    utils::{authorize_delegate, compute_amount_due, settle_repayment, spend_authority},
 };
 
-/// Repay a loan
-pub fn process_repay(ctx: Context<Repay>, loan_idx: u64, rate: u8) -> Result<()> {
-    let _ = rate;
-    let loan_pool = &mut ctx.accounts.loan_pool;
+/// Repay a loan plus any late penalty, signed by the borrower or its delegate.
+/// The payment is held in the vault until the lender side claims it. Basket collateral is
+/// returned between the associated token accounts passed in remaining accounts.
+pub fn process_repay<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Repay<'info>>,
+    loan_id: u64,
+) -> Result<()> {
+    let loan = &mut ctx.accounts.loan;
     let lend_auction = &ctx.accounts.lend_auction;
 
-    require!(
-        loan_idx < loan_pool.loans.len() as u64,
-        ErrorCode::InvalidLoanIndex
-    );
-    let loan = &mut loan_pool.loans[loan_idx as usize];
-
-    require!(!loan.repaid, ErrorCode::AlreadyRepaid);
+    require!(loan.is_open(), ErrorCode::InvalidLoanStatus);
     require_eq!(
         loan.borrower,
         *ctx.accounts.borrower.key,
@@ -35,9 +34,9 @@ pub fn process_repay(ctx: Context<Repay>, loan_idx: u64, rate: u8) -> Result<()>
         ErrorCode::InvalidRepaymentToken
     );
     require_eq!(
-        ctx.accounts.lender_token_account.mint,
+        ctx.accounts.vault_token_account.mint,
         loan.token_mint,
-        ErrorCode::InvalidTokenAccount
+        ErrorCode::InvalidVaultAccount
     );
     require_eq!(
         ctx.accounts.vault_collateral_account.mint,
@@ -45,56 +44,47 @@ pub fn process_repay(ctx: Context<Repay>, loan_idx: u64, rate: u8) -> Result<()>
         ErrorCode::InvalidVaultAccount
     );
 
-    let current_slot = Clock::get()?.slot;
-    let elapsed_slots = current_slot.saturating_sub(loan.start_slot);
-    let interest_factor = (elapsed_slots as u128)
-        .checked_mul(loan.rate as u128)
-        .ok_or(ErrorCode::Overflow)?
-        .checked_div(
-            (loan.duration_slots as u128)
-                .checked_mul(100)
-                .ok_or(ErrorCode::Overflow)?,
-        )
-        .ok_or(ErrorCode::Overflow)?;
-    let repayment = loan
-        .amount
-        .checked_add(
-            (loan.amount as u128)
-                .checked_mul(interest_factor)
-                .ok_or(ErrorCode::Overflow)? as u64,
-        )
-        .ok_or(ErrorCode::Overflow)?;
-
-    transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.borrower_token_account.to_account_info(),
-                to: ctx.accounts.lender_token_account.to_account_info(),
-                authority: ctx.accounts.borrower.to_account_info(),
-            },
-        ),
-        repayment,
+    let (repayment, penalty) = compute_amount_due(
+        loan,
+        ctx.accounts.market.penalty_rate,
+        Clock::get()?.unix_timestamp,
     )?;
-
-    transfer(
-        CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.vault_collateral_account.to_account_info(),
-                to: ctx.accounts.borrower_collateral_account.to_account_info(),
-                authority: lend_auction.to_account_info(),
-            },
-            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
-        ),
-        loan.collateral,
+    let total_due = repayment.checked_add(penalty).ok_or(ErrorCode::Overflow)?;
+    authorize_delegate(
+        &loan.borrower,
+        ctx.accounts.authority.key,
+        ctx.accounts.delegate.as_mut(),
+        DELEGATE_REPAY,
+        &[(loan.token_mint, total_due)],
+    )?;
+    let payer = spend_authority(
+        &loan.borrower,
+        &ctx.accounts.authority.to_account_info(),
+        &lend_auction.to_account_info(),
+    );
+    settle_repayment(
+        loan,
+        total_due,
+        &payer,
+        &ctx.accounts.borrower_token_account.to_account_info(),
+        &ctx.accounts.vault_token_account.to_account_info(),
+        &ctx.accounts.vault_collateral_account.to_account_info(),
+        &ctx.accounts.borrower_collateral_account.to_account_info(),
+        &mut ctx.accounts.lender_positions,
+        &mut ctx.accounts.borrower_positions,
+        ctx.remaining_accounts,
+        &lend_auction.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
     )?;
 
-    loan.repaid = true;
     emit!(LoanRepaid {
+        loan_id,
         lender: loan.lender,
         borrower: loan.borrower,
+        payer: loan.borrower,
         amount: repayment,
+        penalty,
         shard_id: loan.shard_id,
         token_mint: loan.token_mint,
         collateral_mint: loan.collateral_mint,
@@ -102,25 +92,45 @@ pub fn process_repay(ctx: Context<Repay>, loan_idx: u64, rate: u8) -> Result<()>
     Ok(())
 }
 #[derive(Accounts)]
-#[instruction(loan_idx: u64, rate: u8)]
+#[instruction(loan_id: u64)]
 pub struct Repay<'info> {
     #[account(mut, seeds = [b"lend_auction"], bump)]
     pub lend_auction: Account<'info, LendAuction>,
-    #[account(mut, seeds = [b"loan_pool",  &compute_shard_id(&token_mint.key(), rate, lend_auction.shard_count).to_le_bytes()[..]], bump)]
-    pub loan_pool: Account<'info, LoanPool>,
+    #[account(
+        mut,
+        seeds = [b"loan", loan_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub loan: Account<'info, Loan>,
+    #[account(seeds = [b"market", loan.token_mint.as_ref()], bump)]
+    pub market: Account<'info, Market>,
+    #[account(mut, seeds = [b"user_positions", loan.lender.as_ref()], bump)]
+    pub lender_positions: Box<Account<'info, UserPositions>>,
+    #[account(mut, seeds = [b"user_positions", loan.borrower.as_ref()], bump)]
+    pub borrower_positions: Box<Account<'info, UserPositions>>,
     #[account(mut)]
-    pub borrower: Signer<'info>,
+    pub authority: Signer<'info>,
+    /// CHECK: Loan borrower, validated against the loan and the signing authority or its delegate
+    pub borrower: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"delegate", borrower.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>,
     #[account(mut, constraint = borrower_token_account.owner == borrower.key())]
     pub borrower_token_account: Account<'info, TokenAccount>,
     #[account(mut, constraint = borrower_collateral_account.owner == borrower.key())]
     pub borrower_collateral_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub lender_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_token_account.owner == lend_auction.key()
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
     #[account(
         mut,
         constraint = vault_collateral_account.owner == lend_auction.key()
     )]
     pub vault_collateral_account: Account<'info, TokenAccount>,
-    pub token_mint: Box<Account<'info, Mint>>,
     pub token_program: Program<'info, Token>,
 }
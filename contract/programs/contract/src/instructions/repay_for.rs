@@ -0,0 +1,113 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Token, TokenAccount};
+
+use crate::{
+    errors::ErrorCode,
+    events::LoanRepaid,
+    states::{LendAuction, Loan, Market, UserPositions},
+    utils::{compute_amount_due, settle_repayment},
+};
+
+/// Repay a loan plus any late penalty on the borrower's behalf from any payer's funds.
+/// Collateral is still released only to the borrower, basket components between the
+/// associated token accounts passed in remaining accounts.
+pub fn process_repay_for<'info>(
+    ctx: Context<'_, '_, 'info, 'info, RepayFor<'info>>,
+    loan_id: u64,
+) -> Result<()> {
+    let loan = &mut ctx.accounts.loan;
+    let lend_auction = &ctx.accounts.lend_auction;
+
+    require!(loan.is_open(), ErrorCode::InvalidLoanStatus);
+    require!(
+        lend_auction.supported_tokens.contains(&loan.token_mint),
+        ErrorCode::UnsupportedToken
+    );
+    require_eq!(
+        ctx.accounts.payer_token_account.mint,
+        loan.token_mint,
+        ErrorCode::InvalidRepaymentToken
+    );
+    require_eq!(
+        ctx.accounts.borrower_collateral_account.mint,
+        loan.collateral_mint,
+        ErrorCode::InvalidTokenAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_token_account.mint,
+        loan.token_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_collateral_account.mint,
+        loan.collateral_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    // Same amount owed as a repayment by the borrower
+    let (repayment, penalty) = compute_amount_due(
+        loan,
+        ctx.accounts.market.penalty_rate,
+        Clock::get()?.unix_timestamp,
+    )?;
+    let total_due = repayment.checked_add(penalty).ok_or(ErrorCode::Overflow)?;
+    settle_repayment(
+        loan,
+        total_due,
+        &ctx.accounts.payer.to_account_info(),
+        &ctx.accounts.payer_token_account.to_account_info(),
+        &ctx.accounts.vault_token_account.to_account_info(),
+        &ctx.accounts.vault_collateral_account.to_account_info(),
+        &ctx.accounts.borrower_collateral_account.to_account_info(),
+        &mut ctx.accounts.lender_positions,
+        &mut ctx.accounts.borrower_positions,
+        ctx.remaining_accounts,
+        &lend_auction.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+    )?;
+
+    emit!(LoanRepaid {
+        loan_id,
+        lender: loan.lender,
+        borrower: loan.borrower,
+        payer: ctx.accounts.payer.key(),
+        amount: repayment,
+        penalty,
+        shard_id: loan.shard_id,
+        token_mint: loan.token_mint,
+        collateral_mint: loan.collateral_mint,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct RepayFor<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(mut, seeds = [b"loan", loan_id.to_le_bytes().as_ref()], bump)]
+    pub loan: Account<'info, Loan>,
+    #[account(seeds = [b"market", loan.token_mint.as_ref()], bump)]
+    pub market: Account<'info, Market>,
+    #[account(mut, seeds = [b"user_positions", loan.lender.as_ref()], bump)]
+    pub lender_positions: Box<Account<'info, UserPositions>>,
+    #[account(mut, seeds = [b"user_positions", loan.borrower.as_ref()], bump)]
+    pub borrower_positions: Box<Account<'info, UserPositions>>,
+    pub payer: Signer<'info>,
+    #[account(mut, constraint = payer_token_account.owner == payer.key())]
+    pub payer_token_account: Account<'info, TokenAccount>,
+    #[account(mut, constraint = borrower_collateral_account.owner == loan.borrower)]
+    pub borrower_collateral_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_token_account.owner == lend_auction.key()
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_collateral_account.owner == lend_auction.key()
+    )]
+    pub vault_collateral_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
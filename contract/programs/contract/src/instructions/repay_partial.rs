@@ -0,0 +1,214 @@
+use std::cmp;
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer, Token, TokenAccount, Transfer};
+
+use crate::{
+    errors::ErrorCode,
+    events::LoanPartiallyRepaid,
+    states::{Delegate, LendAuction, Loan, Market, UserPositions, DELEGATE_REPAY},
+    utils::{
+        authorize_delegate, collateral_value, compute_penalty, compute_repayment,
+        reduce_loan_position, repayment_accrual_ts, spend_authority,
+    },
+};
+
+/// Repay part of a loan, signed by the borrower or its delegate, crediting the lender's claimable balance.
+/// Payment covers accrued interest and penalty first, then principal. When `release_collateral`
+/// is set, collateral is returned pro-rata to principal repaid, capped to keep the balance still
+/// owed at the market's initial collateral ratio, valued the same way as `withdraw_collateral`.
+/// Remaining accounts hold the collateral configs of the loan's mints when it has a basket.
+pub fn process_repay_partial<'info>(
+    ctx: Context<'_, '_, 'info, 'info, RepayPartial<'info>>,
+    loan_id: u64,
+    amount: u64,
+    release_collateral: bool,
+) -> Result<()> {
+    let loan = &mut ctx.accounts.loan;
+    let lend_auction = &ctx.accounts.lend_auction;
+
+    require!(amount > 0, ErrorCode::InvalidAmount);
+    require!(loan.is_open(), ErrorCode::InvalidLoanStatus);
+    require_eq!(
+        loan.borrower,
+        *ctx.accounts.borrower.key,
+        ErrorCode::Unauthorized
+    );
+    require_eq!(
+        ctx.accounts.borrower_token_account.mint,
+        loan.token_mint,
+        ErrorCode::InvalidRepaymentToken
+    );
+    require_eq!(
+        ctx.accounts.vault_token_account.mint,
+        loan.token_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_collateral_account.mint,
+        loan.collateral_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    // Checkpoint accrued interest and penalty, owing interest up to the minimum-interest horizon
+    let now = Clock::get()?.unix_timestamp;
+    let accrual_ts = repayment_accrual_ts(loan, now)?;
+    let interest_due = compute_repayment(loan, accrual_ts)?
+        .checked_sub(loan.amount)
+        .ok_or(ErrorCode::Overflow)?;
+    let penalty_due = compute_penalty(loan, ctx.accounts.market.penalty_rate, now)?;
+    let total_due = loan
+        .amount
+        .checked_add(interest_due)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_add(penalty_due)
+        .ok_or(ErrorCode::Overflow)?;
+    require_gt!(total_due, amount, ErrorCode::PartialRepaymentTooLarge);
+
+    authorize_delegate(
+        &loan.borrower,
+        ctx.accounts.authority.key,
+        ctx.accounts.delegate.as_mut(),
+        DELEGATE_REPAY,
+        &[(loan.token_mint, amount)],
+    )?;
+
+    // Apply payment to penalty and interest first, then principal
+    let penalty_paid = cmp::min(amount, penalty_due);
+    let interest_paid = cmp::min(amount - penalty_paid, interest_due);
+    let principal_paid = amount - penalty_paid - interest_paid;
+    let remaining_amount = loan.amount - principal_paid;
+
+    let collateral_released = if release_collateral && principal_paid > 0 {
+        let pro_rata = (loan.collateral as u128)
+            .checked_mul(principal_paid as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(loan.amount as u128)
+            .ok_or(ErrorCode::Overflow)? as u64;
+        // Primary collateral counts one for one in the loan's value, so whatever the basket
+        // adds, releasing up to the surplus over the rounded-up requirement keeps the health
+        // factor of the balance still owed at the initial collateral ratio
+        let value = collateral_value(
+            ctx.remaining_accounts,
+            &loan.collateral_mint,
+            loan.collateral,
+            &loan.basket,
+        )?;
+        let required = ((total_due - amount) as u128)
+            .checked_mul(ctx.accounts.market.initial_collateral_ratio as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .div_ceil(100);
+        let surplus = u64::try_from((value as u128).saturating_sub(required))
+            .map_err(|_| ErrorCode::Overflow)?;
+        cmp::min(pro_rata, cmp::min(surplus, loan.collateral))
+    } else {
+        0
+    };
+
+    transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.borrower_token_account.to_account_info(),
+                to: ctx.accounts.vault_token_account.to_account_info(),
+                authority: spend_authority(
+                    &loan.borrower,
+                    &ctx.accounts.authority.to_account_info(),
+                    &lend_auction.to_account_info(),
+                ),
+            },
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+        ),
+        amount,
+    )?;
+
+    if collateral_released > 0 {
+        transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault_collateral_account.to_account_info(),
+                    to: ctx.accounts.borrower_collateral_account.to_account_info(),
+                    authority: lend_auction.to_account_info(),
+                },
+                &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+            ),
+            collateral_released,
+        )?;
+    }
+
+    reduce_loan_position(
+        &mut ctx.accounts.lender_positions,
+        loan,
+        principal_paid,
+        collateral_released,
+    )?;
+    reduce_loan_position(
+        &mut ctx.accounts.borrower_positions,
+        loan,
+        principal_paid,
+        collateral_released,
+    )?;
+
+    loan.accrued_interest = interest_due - interest_paid;
+    loan.accrued_penalty = penalty_due - penalty_paid;
+    loan.last_accrual_ts = accrual_ts;
+    loan.amount = remaining_amount;
+    loan.claimable = loan
+        .claimable
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+    loan.collateral -= collateral_released;
+
+    emit!(LoanPartiallyRepaid {
+        loan_id,
+        lender: loan.lender,
+        borrower: loan.borrower,
+        interest_paid: interest_paid + penalty_paid,
+        principal_paid,
+        remaining_amount,
+        collateral_released,
+        shard_id: loan.shard_id,
+        token_mint: loan.token_mint,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct RepayPartial<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(mut, seeds = [b"loan", loan_id.to_le_bytes().as_ref()], bump)]
+    pub loan: Account<'info, Loan>,
+    #[account(seeds = [b"market", loan.token_mint.as_ref()], bump)]
+    pub market: Account<'info, Market>,
+    #[account(mut, seeds = [b"user_positions", loan.lender.as_ref()], bump)]
+    pub lender_positions: Box<Account<'info, UserPositions>>,
+    #[account(mut, seeds = [b"user_positions", loan.borrower.as_ref()], bump)]
+    pub borrower_positions: Box<Account<'info, UserPositions>>,
+    pub authority: Signer<'info>,
+    /// CHECK: Loan borrower, validated against the loan and the signing authority or its delegate
+    pub borrower: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"delegate", borrower.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>,
+    #[account(mut, constraint = borrower_token_account.owner == borrower.key())]
+    pub borrower_token_account: Account<'info, TokenAccount>,
+    #[account(mut, constraint = borrower_collateral_account.owner == borrower.key())]
+    pub borrower_collateral_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_token_account.owner == lend_auction.key()
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_collateral_account.owner == lend_auction.key()
+    )]
+    pub vault_collateral_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
@@ -0,0 +1,249 @@
+use std::cmp;
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+
+use crate::utils::{
+    close_loan_position, compute_amount_due, create_raydium_swap_base_out_instruction,
+    transfer_basket,
+};
+use crate::{
+    errors::ErrorCode,
+    events::LoanRepaidWithCollateral,
+    states::{LendAuction, Loan, LoanStatus, Market, UserPositions},
+    RAYDIUM_AMM_PROGRAM,
+};
+
+/// Repay a loan by swapping just enough of its collateral into the loan token on Raydium.
+/// The borrower caps the collateral sold; whatever is left is returned to the borrower, along
+/// with the basket between the associated token accounts passed in remaining accounts.
+pub fn process_repay_with_collateral<'info>(
+    ctx: Context<'_, '_, 'info, 'info, RepayWithCollateral<'info>>,
+    loan_id: u64,
+    max_collateral_in: u64,
+) -> Result<()> {
+    let loan = &mut ctx.accounts.loan;
+    let lend_auction = &ctx.accounts.lend_auction;
+
+    require!(loan.is_open(), ErrorCode::InvalidLoanStatus);
+    require_keys_eq!(
+        loan.borrower,
+        ctx.accounts.borrower.key(),
+        ErrorCode::Unauthorized
+    );
+    require!(
+        lend_auction.supported_tokens.contains(&loan.token_mint),
+        ErrorCode::UnsupportedToken
+    );
+    require_eq!(
+        ctx.accounts.borrower_collateral_account.mint,
+        loan.collateral_mint,
+        ErrorCode::InvalidTokenAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_token_account.mint,
+        loan.token_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_collateral_account.mint,
+        loan.collateral_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    // Same amount owed as a repayment from the borrower's own funds
+    let (repayment, penalty) = compute_amount_due(
+        loan,
+        ctx.accounts.market.penalty_rate,
+        Clock::get()?.unix_timestamp,
+    )?;
+    let total_due = repayment.checked_add(penalty).ok_or(ErrorCode::Overflow)?;
+
+    // Only this loan's collateral may be sold out of the shared vault
+    let max_amount_in = cmp::min(max_collateral_in, loan.collateral);
+    let collateral_before = ctx.accounts.vault_collateral_account.amount;
+    let proceeds_before = ctx.accounts.vault_token_account.amount;
+
+    // Perform Raydium swap: collateral -> exactly the amount due in loan token
+    let swap_instruction = create_raydium_swap_base_out_instruction(
+        &ctx.accounts.raydium_amm_program,
+        &ctx.accounts.amm,
+        &ctx.accounts.amm_authority,
+        &ctx.accounts.amm_open_orders,
+        &ctx.accounts.amm_coin_vault,
+        &ctx.accounts.amm_pc_vault,
+        &ctx.accounts.market_program,
+        &ctx.accounts.serum_market,
+        &ctx.accounts.market_bids,
+        &ctx.accounts.market_asks,
+        &ctx.accounts.market_event_queue,
+        &ctx.accounts.market_coin_vault,
+        &ctx.accounts.market_pc_vault,
+        &ctx.accounts.market_vault_signer,
+        &ctx.accounts.vault_collateral_account.to_account_info(), // Source: collateral
+        &ctx.accounts.vault_token_account.to_account_info(),      // Destination: loan token
+        &lend_auction.to_account_info(),                          // Authority
+        max_amount_in,
+        total_due,
+    )?;
+
+    anchor_lang::solana_program::program::invoke_signed(
+        &swap_instruction,
+        &[
+            ctx.accounts.raydium_amm_program.to_account_info(),
+            ctx.accounts.amm.to_account_info(),
+            ctx.accounts.amm_authority.to_account_info(),
+            ctx.accounts.amm_open_orders.to_account_info(),
+            ctx.accounts.amm_coin_vault.to_account_info(),
+            ctx.accounts.amm_pc_vault.to_account_info(),
+            ctx.accounts.market_program.to_account_info(),
+            ctx.accounts.serum_market.to_account_info(),
+            ctx.accounts.market_bids.to_account_info(),
+            ctx.accounts.market_asks.to_account_info(),
+            ctx.accounts.market_event_queue.to_account_info(),
+            ctx.accounts.market_coin_vault.to_account_info(),
+            ctx.accounts.market_pc_vault.to_account_info(),
+            ctx.accounts.market_vault_signer.to_account_info(),
+            ctx.accounts.vault_collateral_account.to_account_info(),
+            ctx.accounts.vault_token_account.to_account_info(),
+            ctx.accounts.lend_auction.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        ],
+        &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+    )?;
+
+    ctx.accounts.vault_collateral_account.reload()?;
+    ctx.accounts.vault_token_account.reload()?;
+    let collateral_sold = collateral_before
+        .checked_sub(ctx.accounts.vault_collateral_account.amount)
+        .ok_or(ErrorCode::Overflow)?;
+    let proceeds = ctx
+        .accounts
+        .vault_token_account
+        .amount
+        .checked_sub(proceeds_before)
+        .ok_or(ErrorCode::Overflow)?;
+    require_gte!(
+        max_amount_in,
+        collateral_sold,
+        ErrorCode::InsufficientCollateral
+    );
+    require_gte!(proceeds, total_due, ErrorCode::InsufficientSwapProceeds);
+
+    loan.claimable = loan
+        .claimable
+        .checked_add(proceeds)
+        .ok_or(ErrorCode::Overflow)?;
+    loan.status = LoanStatus::Repaid;
+
+    // Return the unsold collateral
+    let collateral_returned = loan.collateral - collateral_sold;
+    if collateral_returned > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault_collateral_account.to_account_info(),
+                    to: ctx.accounts.borrower_collateral_account.to_account_info(),
+                    authority: lend_auction.to_account_info(),
+                },
+                &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+            ),
+            collateral_returned,
+        )?;
+    }
+    transfer_basket(
+        ctx.remaining_accounts,
+        &loan.basket,
+        &lend_auction.key(),
+        &loan.borrower,
+        &lend_auction.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+    )?;
+
+    close_loan_position(&mut ctx.accounts.lender_positions, loan)?;
+    close_loan_position(&mut ctx.accounts.borrower_positions, loan)?;
+
+    emit!(LoanRepaidWithCollateral {
+        loan_id,
+        lender: loan.lender,
+        borrower: loan.borrower,
+        amount: repayment,
+        penalty,
+        collateral_sold,
+        collateral_returned,
+        shard_id: loan.shard_id,
+        token_mint: loan.token_mint,
+        collateral_mint: loan.collateral_mint,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct RepayWithCollateral<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(mut, seeds = [b"loan", loan_id.to_le_bytes().as_ref()], bump)]
+    pub loan: Account<'info, Loan>,
+    #[account(seeds = [b"market", loan.token_mint.as_ref()], bump)]
+    pub market: Account<'info, Market>,
+    #[account(mut, seeds = [b"user_positions", loan.lender.as_ref()], bump)]
+    pub lender_positions: Box<Account<'info, UserPositions>>,
+    #[account(mut, seeds = [b"user_positions", loan.borrower.as_ref()], bump)]
+    pub borrower_positions: Box<Account<'info, UserPositions>>,
+    pub borrower: Signer<'info>,
+    #[account(mut, constraint = borrower_collateral_account.owner == borrower.key())]
+    pub borrower_collateral_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_token_account.owner == lend_auction.key()
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_collateral_account.owner == lend_auction.key()
+    )]
+    pub vault_collateral_account: Account<'info, TokenAccount>,
+    /// CHECK: Raydium AMM Program
+    #[account(constraint = raydium_amm_program.key() == RAYDIUM_AMM_PROGRAM)]
+    pub raydium_amm_program: UncheckedAccount<'info>,
+    /// CHECK: AMM account
+    #[account(mut)]
+    pub amm: UncheckedAccount<'info>,
+    /// CHECK: AMM authority
+    pub amm_authority: UncheckedAccount<'info>,
+    /// CHECK: AMM open orders
+    #[account(mut)]
+    pub amm_open_orders: UncheckedAccount<'info>,
+    /// CHECK: AMM coin vault (collateral)
+    #[account(mut)]
+    pub amm_coin_vault: UncheckedAccount<'info>,
+    /// CHECK: AMM pc vault (loan token)
+    #[account(mut)]
+    pub amm_pc_vault: UncheckedAccount<'info>,
+    /// CHECK: Market program
+    pub market_program: UncheckedAccount<'info>,
+    /// CHECK: Serum market account
+    #[account(mut)]
+    pub serum_market: UncheckedAccount<'info>,
+    /// CHECK: Market bids
+    #[account(mut)]
+    pub market_bids: UncheckedAccount<'info>,
+    /// CHECK: Market asks
+    #[account(mut)]
+    pub market_asks: UncheckedAccount<'info>,
+    /// CHECK: Market event queue
+    #[account(mut)]
+    pub market_event_queue: UncheckedAccount<'info>,
+    /// CHECK: Market coin vault
+    #[account(mut)]
+    pub market_coin_vault: UncheckedAccount<'info>,
+    /// CHECK: Market pc vault
+    #[account(mut)]
+    pub market_pc_vault: UncheckedAccount<'info>,
+    /// CHECK: Market vault signer
+    pub market_vault_signer: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+}
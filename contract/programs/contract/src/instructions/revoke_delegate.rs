@@ -0,0 +1,27 @@
+use anchor_lang::prelude::*;
+
+use crate::events::DelegateRevoked;
+use crate::states::Delegate;
+
+/// Owner revokes a delegate and reclaims its rent
+pub fn process_revoke_delegate(ctx: Context<RevokeDelegate>, delegate: Pubkey) -> Result<()> {
+    emit!(DelegateRevoked {
+        owner: ctx.accounts.owner.key(),
+        delegate,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey)]
+pub struct RevokeDelegate<'info> {
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"delegate", owner.key().as_ref(), delegate.as_ref()],
+        bump
+    )]
+    pub delegate_account: Account<'info, Delegate>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
@@ -0,0 +1,329 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+
+use crate::{
+    errors::ErrorCode,
+    events::{LoanIssued, SignedOrderSettled},
+    math::compute_maturity,
+    states::{
+        CollateralComponent, CollateralConfig, LendAuction, Loan, LoanPool, LoanStatus,
+        MakerBalance, Market, OrderSide, ShardPool, SignedOrder, SignedOrderFill, UserPositions,
+        MAX_BASKET_COMPONENTS,
+    },
+    utils::{
+        compute_shard_id, fit_user_positions, open_loan_position, proceeds_destination,
+        required_collateral, validate_collateral_size, validate_early_repayment,
+        validate_order_size, verify_ed25519_instruction,
+    },
+};
+
+/// Settle a maker order signed off chain against the signing taker.
+/// The preceding instruction must be an Ed25519 sigverify of `SignedOrder::message` by the maker.
+pub fn process_settle_signed_order(
+    ctx: Context<SettleSignedOrder>,
+    order: SignedOrder,
+) -> Result<()> {
+    let lend_auction = &mut ctx.accounts.lend_auction;
+    let shard_pool = &mut ctx.accounts.shard_pool;
+    let loan_pool = &mut ctx.accounts.loan_pool;
+    let loan = &mut ctx.accounts.loan;
+    let maker_balance = &mut ctx.accounts.maker_balance;
+    let order_fill = &mut ctx.accounts.order_fill;
+    let taker = &ctx.accounts.taker;
+    let clock = Clock::get()?;
+    let current_slot = clock.slot;
+
+    // Verify the maker's signature through instruction introspection
+    let instructions_sysvar = ctx.accounts.instructions_sysvar.to_account_info();
+    let current_index = load_current_index_checked(&instructions_sysvar)?;
+    require!(current_index > 0, ErrorCode::InvalidSignature);
+    let ed25519_ix = load_instruction_at_checked(current_index as usize - 1, &instructions_sysvar)?;
+    verify_ed25519_instruction(&ed25519_ix, &order.maker, &order.message()?)?;
+
+    // Replay protection: each nonce fills once, and makers revoke every unfilled order
+    // below a nonce with `cancel_nonces`
+    require_gte!(order.expiry_slot, current_slot, ErrorCode::OrderExpired);
+    require_gte!(
+        order.nonce,
+        maker_balance.min_nonce,
+        ErrorCode::NonceAlreadyUsed
+    );
+    require!(!order_fill.filled, ErrorCode::NonceAlreadyUsed);
+    order_fill.filled = true;
+
+    // Validate inputs
+    require_keys_neq!(order.maker, taker.key(), ErrorCode::Unauthorized);
+    require!(order.amount > 0, ErrorCode::InvalidAmount);
+    require!(order.collateral > 0, ErrorCode::InvalidCollateral);
+    require!(order.duration_slots > 0, ErrorCode::InvalidDuration);
+    validate_early_repayment(order.early_repayment, order.duration_slots)?;
+    validate_order_size(&ctx.accounts.market, order.amount)?;
+    validate_collateral_size(&ctx.accounts.collateral_config, order.collateral)?;
+    require!(
+        lend_auction.supported_tokens.contains(&order.token_mint),
+        ErrorCode::UnsupportedToken
+    );
+    require!(
+        lend_auction
+            .supported_tokens
+            .contains(&order.collateral_mint),
+        ErrorCode::UnsupportedCollateral
+    );
+    require_eq!(
+        ctx.accounts.vault_token_account.mint,
+        order.token_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_collateral_account.mint,
+        order.collateral_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    let shard_id = compute_shard_id(&order.token_mint, order.rate, lend_auction.shard_count);
+
+    // Record shard and rent payer when the pools were created by this instruction
+    if shard_pool.payer == Pubkey::default() {
+        shard_pool.shard_id = shard_id;
+        shard_pool.payer = taker.key();
+    }
+    if loan_pool.payer == Pubkey::default() {
+        loan_pool.shard_id = shard_id;
+        loan_pool.payer = taker.key();
+    }
+    require_eq!(shard_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+    require_eq!(loan_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+
+    // Draw the maker's side from its pre-deposited balance
+    maker_balance.amount = maker_balance
+        .amount
+        .checked_sub(order.funding_amount())
+        .ok_or(ErrorCode::InsufficientBalance)?;
+
+    // The taker funds the opposite side into the vault
+    let (lender, borrower, taker_vault, taker_amount) = match order.side {
+        OrderSide::Bid => (
+            order.maker,
+            taker.key(),
+            ctx.accounts.vault_collateral_account.to_account_info(),
+            order.collateral,
+        ),
+        OrderSide::Ask => (
+            taker.key(),
+            order.maker,
+            ctx.accounts.vault_token_account.to_account_info(),
+            order.amount,
+        ),
+    };
+    // Signed orders carry no payout account, so proceeds go to the borrower's associated token account
+    require_keys_eq!(
+        ctx.accounts.borrower_token_account.key(),
+        proceeds_destination(&borrower, &order.token_mint, None),
+        ErrorCode::InvalidTokenAccount
+    );
+    require_keys_eq!(
+        ctx.accounts.borrower_token_account.mint,
+        order.token_mint,
+        ErrorCode::InvalidTokenAccount
+    );
+    require_eq!(
+        ctx.accounts.taker_funding_account.mint,
+        match order.side {
+            OrderSide::Bid => order.collateral_mint,
+            OrderSide::Ask => order.token_mint,
+        },
+        ErrorCode::InvalidTokenAccount
+    );
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.taker_funding_account.to_account_info(),
+                to: taker_vault,
+                authority: taker.to_account_info(),
+            },
+        ),
+        taker_amount,
+    )?;
+
+    loan.set_inner(Loan {
+        loan_id: lend_auction.total_loans,
+        payer: taker.key(),
+        lender,
+        borrower,
+        amount: order.amount,
+        rate: order.rate,
+        collateral: order.collateral,
+        status: LoanStatus::Active,
+        shard_id,
+        token_mint: order.token_mint,
+        collateral_mint: order.collateral_mint,
+        start_ts: clock.unix_timestamp,
+        maturity_ts: compute_maturity(clock.unix_timestamp, order.duration_slots)?,
+        accrued_interest: 0,
+        accrued_penalty: 0,
+        last_accrual_ts: clock.unix_timestamp,
+        early_repayment: order.early_repayment,
+        position_mint: None,
+        claimable: 0,
+        basket: Vec::new(),
+    });
+
+    // Validate collateral ratio
+    require_gte!(
+        loan.collateral,
+        required_collateral(&ctx.accounts.market, loan.amount)?,
+        ErrorCode::InsufficientCollateral
+    );
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.vault_token_account.to_account_info(),
+                to: ctx.accounts.borrower_token_account.to_account_info(),
+                authority: lend_auction.to_account_info(),
+            },
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+        ),
+        loan.amount,
+    )?;
+
+    // Index the loan for both parties, initializing their position accounts if needed
+    let maker_positions = &mut ctx.accounts.maker_positions;
+    if maker_positions.owner == Pubkey::default() {
+        maker_positions.owner = order.maker;
+    }
+    open_loan_position(maker_positions, loan)?;
+    let taker_positions = &mut ctx.accounts.taker_positions;
+    if taker_positions.owner == Pubkey::default() {
+        taker_positions.owner = taker.key();
+    }
+    open_loan_position(taker_positions, loan)?;
+    for positions in [&ctx.accounts.maker_positions, &ctx.accounts.taker_positions] {
+        fit_user_positions(
+            positions,
+            &taker.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
+    }
+
+    lend_auction.total_loans = lend_auction
+        .total_loans
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+    loan_pool.active_loans = loan_pool
+        .active_loans
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+
+    emit!(LoanIssued {
+        loan_id: loan.loan_id,
+        lender: loan.lender,
+        borrower: loan.borrower,
+        amount: loan.amount,
+        rate: loan.rate,
+        shard_id,
+        token_mint: loan.token_mint,
+        collateral_mint: loan.collateral_mint,
+    });
+    emit!(SignedOrderSettled {
+        maker: order.maker,
+        taker: taker.key(),
+        side: order.side,
+        nonce: order.nonce,
+        shard_id,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(order: SignedOrder)]
+pub struct SettleSignedOrder<'info> {
+    #[account(mut, seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Box<Account<'info, LendAuction>>,
+    #[account(seeds = [b"market", order.token_mint.as_ref()], bump)]
+    pub market: Box<Account<'info, Market>>,
+    #[account(seeds = [b"collateral_config", order.collateral_mint.as_ref()], bump)]
+    pub collateral_config: Box<Account<'info, CollateralConfig>>,
+    #[account(
+        init_if_needed,
+        payer = taker,
+        space = 8 + 8 + 32 + 4 + 10 * (32 + 8 + 1 + 8 + 32 + 8 + 9 + 33) + 4 + 10 * (32 + 8 + 1 + 8 + 8 + 32 + 32 + 33 + 4 + MAX_BASKET_COMPONENTS * CollateralComponent::SPACE + 33),
+        seeds = [b"shard_pool", &compute_shard_id(&order.token_mint, order.rate, lend_auction.shard_count).to_le_bytes()[..]],
+        bump
+    )]
+    pub shard_pool: Box<Account<'info, ShardPool>>,
+    #[account(
+        init_if_needed,
+        payer = taker,
+        space = 8 + 8 + 32 + 8,
+        seeds = [b"loan_pool", &compute_shard_id(&order.token_mint, order.rate, lend_auction.shard_count).to_le_bytes()[..]],
+        bump
+    )]
+    pub loan_pool: Box<Account<'info, LoanPool>>,
+    #[account(
+        init,
+        payer = taker,
+        space = Loan::SPACE,
+        seeds = [b"loan", lend_auction.total_loans.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub loan: Box<Account<'info, Loan>>,
+    #[account(
+        mut,
+        seeds = [b"maker_balance", order.maker.as_ref(), order.funding_mint().as_ref()],
+        bump
+    )]
+    pub maker_balance: Box<Account<'info, MakerBalance>>,
+    #[account(
+        init_if_needed,
+        payer = taker,
+        space = 8 + 1,
+        seeds = [b"signed_order_fill", maker_balance.key().as_ref(), order.nonce.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub order_fill: Box<Account<'info, SignedOrderFill>>,
+    #[account(
+        init_if_needed,
+        payer = taker,
+        space = UserPositions::SPACE,
+        seeds = [b"user_positions", order.maker.as_ref()],
+        bump
+    )]
+    pub maker_positions: Box<Account<'info, UserPositions>>,
+    #[account(
+        init_if_needed,
+        payer = taker,
+        space = UserPositions::SPACE,
+        seeds = [b"user_positions", taker.key().as_ref()],
+        bump
+    )]
+    pub taker_positions: Box<Account<'info, UserPositions>>,
+    #[account(mut)]
+    pub taker: Signer<'info>,
+    #[account(mut, constraint = taker_funding_account.owner == taker.key())]
+    pub taker_funding_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub borrower_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = vault_token_account.owner == lend_auction.key()
+    )]
+    pub vault_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = vault_collateral_account.owner == lend_auction.key()
+    )]
+    pub vault_collateral_account: Box<Account<'info, TokenAccount>>,
+    /// CHECK: Instructions sysvar used to introspect the Ed25519 signature
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
@@ -5,26 +5,59 @@ use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
 
 use crate::{
     errors::ErrorCode,
-    events::{AskSubmitted, LoanIssued},
-    states::{Ask, Bid, LendAuction, Loan, LoanPool, ShardPool},
-    utils::{compute_shard_id, insert_sorted_ask, insert_sorted_bid, match_ask},
+    events::{AskSubmitted, LoanIssued, SelfTradePrevented},
+    math::compute_maturity,
+    states::{
+        Ask, Bid, CollateralComponent, CollateralConfig, Delegate, LendAuction, Loan, LoanPool,
+        LoanStatus, Market, OrderSide, SelfTradePrevention, ShardPool, UserPositions,
+        DELEGATE_PLACE_ORDERS, MAX_BASKET_COMPONENTS,
+    },
+    utils::{
+        authorize_delegate, charge_delegate, collateral_value, compute_shard_id,
+        create_loan_account, fit_user_positions, insert_sorted_ask, insert_sorted_bid,
+        is_valid_remainder, load_user_positions, match_ask, open_loan_position,
+        proceeds_destination, refund_delegate, required_collateral, spend_authority, split_basket,
+        sync_order_shards, transfer_basket, validate_basket, validate_collateral_size,
+        validate_order_size,
+    },
 };
 
-/// Submit a borrower ask with atomic matching
-pub fn process_submit_ask(
-    ctx: Context<SubmitAsk>,
+/// Submit a borrower ask with atomic matching, signed by the borrower or its delegate.
+/// Remaining accounts hold one uninitialized loan account per match, in match order,
+/// followed by the position accounts of the matched lenders.
+/// Proceeds go to `payout_token_account` when supplied, which is recorded on a resting ask,
+/// otherwise to the asker's associated token account.
+/// Each `basket` component moves from the asker's associated token account to the lend
+/// auction's; both accounts and the mint's collateral config follow in remaining accounts,
+/// along with the config of the primary collateral mint.
+/// A delegate's spend cap is charged only once the ask is funded.
+pub fn process_submit_ask<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SubmitAsk<'info>>,
     amount: u64,
     max_rate: u8,
     collateral: u64,
+    basket: Vec<CollateralComponent>,
+    self_trade_prevention: SelfTradePrevention,
 ) -> Result<()> {
     let lend_auction = &mut ctx.accounts.lend_auction;
     let shard_pool = &mut ctx.accounts.shard_pool;
     let loan_pool = &mut ctx.accounts.loan_pool;
     let asker = &ctx.accounts.asker;
+    let authority = &ctx.accounts.authority;
+    let asker_positions = &mut ctx.accounts.asker_positions;
 
     // Validate inputs
     require!(amount > 0, ErrorCode::InvalidAmount);
     require!(collateral > 0, ErrorCode::InvalidCollateral);
+    validate_order_size(&ctx.accounts.market, amount)?;
+    validate_collateral_size(&ctx.accounts.collateral_config, collateral)?;
+    authorize_delegate(
+        &asker.key(),
+        &authority.key(),
+        ctx.accounts.delegate.as_mut(),
+        DELEGATE_PLACE_ORDERS,
+        &[],
+    )?;
     require!(shard_pool.asks.len() < 10, ErrorCode::PoolFull);
     require!(
         lend_auction
@@ -38,6 +71,11 @@ pub fn process_submit_ask(
             .contains(&ctx.accounts.collateral_mint.key()),
         ErrorCode::UnsupportedCollateral
     );
+    validate_basket(
+        &basket,
+        &ctx.accounts.collateral_mint.key(),
+        &lend_auction.supported_tokens,
+    )?;
     require_eq!(
         ctx.accounts.asker_collateral_account.mint,
         ctx.accounts.collateral_mint.key(),
@@ -48,6 +86,13 @@ pub fn process_submit_ask(
         ctx.accounts.collateral_mint.key(),
         ErrorCode::InvalidVaultAccount
     );
+    if let Some(payout_token_account) = &ctx.accounts.payout_token_account {
+        require_eq!(
+            payout_token_account.mint,
+            ctx.accounts.token_mint.key(),
+            ErrorCode::InvalidTokenAccount
+        );
+    }
 
     // Compute shard_id
     let shard_id = compute_shard_id(
@@ -55,37 +100,144 @@ pub fn process_submit_ask(
         max_rate,
         lend_auction.shard_count,
     );
+
+    // Record shard and rent payer on pools created by this instruction
+    if shard_pool.payer == Pubkey::default() {
+        shard_pool.shard_id = shard_id;
+        shard_pool.payer = authority.key();
+    }
+    if loan_pool.payer == Pubkey::default() {
+        loan_pool.shard_id = shard_id;
+        loan_pool.payer = authority.key();
+    }
     require_eq!(shard_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
     require_eq!(loan_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+    if asker_positions.owner == Pubkey::default() {
+        asker_positions.owner = asker.key();
+    }
+
+    let ask = Ask {
+        borrower: asker.key(),
+        amount,
+        max_rate,
+        collateral,
+        slot: Clock::get()?.slot,
+        token_mint: ctx.accounts.token_mint.key(),
+        collateral_mint: ctx.accounts.collateral_mint.key(),
+        payout: ctx
+            .accounts
+            .payout_token_account
+            .as_ref()
+            .map(|account| account.key()),
+        basket,
+        delegate: if asker.key() == authority.key() {
+            None
+        } else {
+            ctx.accounts
+                .delegate
+                .as_ref()
+                .map(|delegate| delegate.key())
+        },
+    };
+
+    // Match ask with bids atomically
+    let outcome = match_ask(&ask, &mut shard_pool.bids, self_trade_prevention)?;
+    if outcome.taker_cancelled {
+        emit!(SelfTradePrevented {
+            owner: ask.borrower,
+            side: OrderSide::Ask,
+            resting: false,
+            amount,
+            collateral,
+            shard_id,
+            token_mint: ask.token_mint,
+        });
+        return Ok(());
+    }
+
+    // Charge the delegate only for an ask that is funded, whether it matches or rests
+    if let (Some(_), Some(delegate)) = (ask.delegate, ctx.accounts.delegate.as_mut()) {
+        charge_delegate(delegate, &ask.spend())?;
+    }
 
     // Transfer collateral to vault
+    let spender = spend_authority(
+        &asker.key(),
+        &authority.to_account_info(),
+        &lend_auction.to_account_info(),
+    );
     token::transfer(
-        CpiContext::new(
+        CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
             Transfer {
                 from: ctx.accounts.asker_collateral_account.to_account_info(),
                 to: ctx.accounts.vault_collateral_account.to_account_info(),
-                authority: asker.to_account_info(),
+                authority: spender.clone(),
             },
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
         ),
         collateral,
     )?;
+    transfer_basket(
+        ctx.remaining_accounts,
+        &ask.basket,
+        &asker.key(),
+        &lend_auction.key(),
+        &spender,
+        &ctx.accounts.token_program.to_account_info(),
+        &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+    )?;
 
-    let ask = Ask {
-        borrower: asker.key(),
-        amount,
-        max_rate,
-        collateral,
-        slot: Clock::get()?.slot,
-        token_mint: ctx.accounts.token_mint.key(),
-        collateral_mint: ctx.accounts.collateral_mint.key(),
-    };
+    // Refund resting bids cancelled by self-trade prevention
+    for bid in outcome.cancelled {
+        require_eq!(
+            ctx.accounts.borrower_token_account.owner,
+            bid.lender,
+            ErrorCode::MissingRefundAccount
+        );
+        require_eq!(
+            ctx.accounts.vault_token_account.mint,
+            bid.token_mint,
+            ErrorCode::InvalidVaultAccount
+        );
 
-    // Match ask with bids atomically
-    let matches = match_ask(&ask, &mut shard_pool.bids)?;
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault_token_account.to_account_info(),
+                    to: ctx.accounts.borrower_token_account.to_account_info(),
+                    authority: lend_auction.to_account_info(),
+                },
+                &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+            ),
+            bid.amount,
+        )?;
+        if let Some(delegate) = ctx
+            .accounts
+            .delegate
+            .as_mut()
+            .filter(|delegate| bid.delegate == Some(delegate.key()))
+        {
+            refund_delegate(delegate, &bid.spend());
+        }
+
+        emit!(SelfTradePrevented {
+            owner: bid.lender,
+            side: OrderSide::Bid,
+            resting: true,
+            amount: bid.amount,
+            collateral: 0,
+            shard_id,
+            token_mint: bid.token_mint,
+        });
+    }
+
+    let matches = outcome.matches;
     if !matches.is_empty() {
         let mut total_matched = 0;
         let mut loans = Vec::new();
+        let now = Clock::get()?.unix_timestamp;
 
         for (bid, rate) in matches {
             let loan_amount = cmp::min(ask.amount - total_matched, bid.amount);
@@ -96,27 +248,40 @@ pub fn process_submit_ask(
                 .checked_div(ask.amount)
                 .ok_or(ErrorCode::Overflow)?;
             let loan = Loan {
+                loan_id: lend_auction
+                    .total_loans
+                    .checked_add(loans.len() as u64)
+                    .ok_or(ErrorCode::Overflow)?,
+                payer: authority.key(),
                 lender: bid.lender,
                 borrower: ask.borrower,
                 amount: loan_amount,
                 rate,
                 collateral: loan_collateral,
-                repaid: false,
+                status: LoanStatus::Active,
                 shard_id,
                 token_mint: ask.token_mint,
                 collateral_mint: ask.collateral_mint,
-                start_slot: Clock::get()?.slot,
-                duration_slots: bid.duration_slots,
+                start_ts: now,
+                maturity_ts: compute_maturity(now, bid.duration_slots)?,
+                accrued_interest: 0,
+                accrued_penalty: 0,
+                last_accrual_ts: now,
+                early_repayment: bid.early_repayment,
+                position_mint: None,
+                claimable: 0,
+                basket: split_basket(&ask.basket, loan_amount, ask.amount)?,
             };
 
-            // Validate collateral ratio
+            // Validate collateral ratio over the whole basket
             require_gte!(
-                loan.collateral,
-                loan.amount
-                    .checked_mul(15)
-                    .ok_or(ErrorCode::Overflow)?
-                    .checked_div(10)
-                    .ok_or(ErrorCode::Overflow)?,
+                collateral_value(
+                    ctx.remaining_accounts,
+                    &loan.collateral_mint,
+                    loan.collateral,
+                    &loan.basket,
+                )?,
+                required_collateral(&ctx.accounts.market, loan.amount)?,
                 ErrorCode::InsufficientCollateral
             );
 
@@ -127,6 +292,10 @@ pub fn process_submit_ask(
 
             // Reinsert remaining bid amount
             if bid.amount > loan_amount {
+                require!(
+                    is_valid_remainder(&ctx.accounts.market, bid.amount - loan_amount),
+                    ErrorCode::OrderTooSmall
+                );
                 let remaining_bid = Bid {
                     amount: bid.amount - loan_amount,
                     ..bid
@@ -138,8 +307,22 @@ pub fn process_submit_ask(
         // Require full match for atomicity
         require_eq!(total_matched, ask.amount, ErrorCode::PartialMatchNotAllowed);
 
-        // Process all transfers and store loans
-        for loan in &loans {
+        // Process all transfers and create one loan account per match
+        require_keys_eq!(
+            ctx.accounts.borrower_token_account.key(),
+            proceeds_destination(&ask.borrower, &ask.token_mint, ask.payout),
+            ErrorCode::InvalidTokenAccount
+        );
+        require_eq!(
+            ctx.accounts.borrower_token_account.mint,
+            ask.token_mint,
+            ErrorCode::InvalidTokenAccount
+        );
+        require!(
+            ctx.remaining_accounts.len() >= loans.len(),
+            ErrorCode::InvalidLoanAccount
+        );
+        for (loan, loan_account) in loans.iter().zip(ctx.remaining_accounts) {
             token::transfer(
                 CpiContext::new_with_signer(
                     ctx.accounts.token_program.to_account_info(),
@@ -153,13 +336,34 @@ pub fn process_submit_ask(
                 loan.amount,
             )?;
 
+            create_loan_account(
+                loan_account,
+                &authority.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                loan,
+            )?;
+            open_loan_position(asker_positions, loan)?;
+            let mut lender_positions = load_user_positions(ctx.remaining_accounts, &loan.lender)?;
+            open_loan_position(&mut lender_positions, loan)?;
+            sync_order_shards(&mut lender_positions, shard_pool)?;
+            fit_user_positions(
+                &lender_positions,
+                &authority.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+            )?;
+            lender_positions.exit(&crate::ID)?;
+
             lend_auction.total_loans = lend_auction
                 .total_loans
                 .checked_add(1)
                 .ok_or(ErrorCode::Overflow)?;
-            loan_pool.loans.push(loan.clone());
+            loan_pool.active_loans = loan_pool
+                .active_loans
+                .checked_add(1)
+                .ok_or(ErrorCode::Overflow)?;
 
             emit!(LoanIssued {
+                loan_id: loan.loan_id,
                 lender: loan.lender,
                 borrower: loan.borrower,
                 amount: loan.amount,
@@ -178,39 +382,70 @@ pub fn process_submit_ask(
             shard_id,
             token_mint: ask.token_mint,
             collateral_mint: ask.collateral_mint,
+            basket: ask.basket.clone(),
         });
     }
 
+    // Index the owner's resting order, or drop shards emptied by self-trade prevention
+    sync_order_shards(asker_positions, shard_pool)?;
+    fit_user_positions(
+        asker_positions,
+        &authority.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+    )?;
+
     Ok(())
 }
 
 #[derive(Accounts)]
-#[instruction(amount: u64, max_rate: u8, collateral: u64)]
+#[instruction(amount: u64, max_rate: u8, collateral: u64, basket: Vec<CollateralComponent>, self_trade_prevention: SelfTradePrevention)]
 pub struct SubmitAsk<'info> {
     #[account(mut, seeds = [b"lend_auction"], bump)]
     pub lend_auction: Box<Account<'info, LendAuction>>,
+    #[account(seeds = [b"market", token_mint.key().as_ref()], bump)]
+    pub market: Box<Account<'info, Market>>,
+    #[account(seeds = [b"collateral_config", collateral_mint.key().as_ref()], bump)]
+    pub collateral_config: Box<Account<'info, CollateralConfig>>,
     #[account(
         init_if_needed,
-        payer = asker,
-        space = 8 + 8 + 10 * (32 + 8 + 1 + 8 + 32 + 8) + 10 * (32 + 8 + 1 + 8 + 8 + 32),
+        payer = authority,
+        space = 8 + 8 + 32 + 4 + 10 * (32 + 8 + 1 + 8 + 32 + 8 + 9 + 33) + 4 + 10 * (32 + 8 + 1 + 8 + 8 + 32 + 32 + 33 + 4 + MAX_BASKET_COMPONENTS * CollateralComponent::SPACE + 33),
         seeds = [b"shard_pool", &compute_shard_id(&token_mint.key(), max_rate, lend_auction.shard_count).to_le_bytes()[..]], // Compute in function
         bump
     )]
     pub shard_pool: Box<Account<'info, ShardPool>>,
     #[account(
         init_if_needed,
-        payer = asker,
-        space = 8 + 8 + 10 * (32 + 32 + 8 + 1 + 8 + 1 + 8 + 32 + 32 + 8 + 8),
+        payer = authority,
+        space = 8 + 8 + 32 + 8,
         seeds = [b"loan_pool", &compute_shard_id(&token_mint.key(), max_rate, lend_auction.shard_count).to_le_bytes()[..]], // Compute in function
         bump
     )]
     pub loan_pool: Box<Account<'info, LoanPool>>,
     #[account(mut)]
-    pub asker: Signer<'info>,
+    pub authority: Signer<'info>,
+    /// CHECK: Order owner, validated against the signing authority or its delegate
+    pub asker: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"delegate", asker.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = UserPositions::SPACE,
+        seeds = [b"user_positions", asker.key().as_ref()],
+        bump
+    )]
+    pub asker_positions: Box<Account<'info, UserPositions>>,
     #[account(mut, constraint = asker_collateral_account.owner == asker.key())]
     pub asker_collateral_account: Box<Account<'info, TokenAccount>>,
     #[account(mut)]
     pub borrower_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(constraint = payout_token_account.owner == asker.key())]
+    pub payout_token_account: Option<Box<Account<'info, TokenAccount>>>,
     #[account(
         mut,
         constraint = vault_token_account.owner == lend_auction.key()
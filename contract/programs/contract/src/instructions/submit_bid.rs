@@ -2,28 +2,60 @@ use std::cmp;
 
 use crate::{
     errors::ErrorCode,
-    events::{BidSubmitted, LoanIssued},
-    states::{Bid, LendAuction, Loan, LoanPool, ShardPool},
-    utils::{compute_shard_id, insert_sorted_bid, match_bid},
+    events::{BidSubmitted, LoanIssued, SelfTradePrevented},
+    math::compute_maturity,
+    states::{
+        Ask, Bid, CollateralComponent, Delegate, EarlyRepayment, LendAuction, Loan, LoanPool,
+        LoanStatus, Market, OrderSide, SelfTradePrevention, ShardPool, UserPositions,
+        DELEGATE_PLACE_ORDERS, MAX_BASKET_COMPONENTS,
+    },
+    utils::{
+        authorize_delegate, charge_delegate, collateral_value, compute_shard_id,
+        create_loan_account, fit_user_positions, insert_sorted_ask, insert_sorted_bid,
+        is_valid_remainder, load_collateral_config, load_proceeds_account, load_user_positions,
+        match_bid, open_loan_position, proceeds_destination, refund_delegate, required_collateral,
+        spend_authority, split_basket, sync_order_shards, transfer_basket,
+        validate_collateral_size, validate_early_repayment, validate_order_size,
+    },
 };
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
 
-/// Submit a lender bid with automatic shard routing
-pub fn process_submit_bid(
-    ctx: Context<SubmitBid>,
+/// Submit a lender bid with automatic shard routing, signed by the lender or its delegate.
+/// Remaining accounts hold one uninitialized loan account per match, in match order,
+/// followed by the position accounts and proceeds accounts of the matched borrowers.
+/// Each borrower is paid to the payout account on its ask, or else its associated token account.
+/// Collateral configs of the mints of matched asks with a basket, the collateral config of a partially filled
+/// ask, whose remaining collateral must stay within the mint's size limits, and the associated token accounts
+/// of baskets refunded by self-trade prevention, are also passed in remaining accounts.
+/// A delegate's spend cap is charged only once the bid is funded.
+pub fn process_submit_bid<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SubmitBid<'info>>,
     amount: u64,
     min_rate: u8,
     duration_slots: u64,
+    early_repayment: EarlyRepayment,
+    self_trade_prevention: SelfTradePrevention,
 ) -> Result<()> {
     let lend_auction = &mut ctx.accounts.lend_auction;
     let shard_pool = &mut ctx.accounts.shard_pool;
     let loan_pool = &mut ctx.accounts.loan_pool;
     let bidder = &ctx.accounts.bidder;
+    let authority = &ctx.accounts.authority;
+    let bidder_positions = &mut ctx.accounts.bidder_positions;
 
     // Validate inputs
     require!(amount > 0, ErrorCode::InvalidAmount);
     require!(duration_slots > 0, ErrorCode::InvalidDuration);
+    validate_early_repayment(early_repayment, duration_slots)?;
+    validate_order_size(&ctx.accounts.market, amount)?;
+    authorize_delegate(
+        &bidder.key(),
+        &authority.key(),
+        ctx.accounts.delegate.as_mut(),
+        DELEGATE_PLACE_ORDERS,
+        &[],
+    )?;
     require!(shard_pool.bids.len() < 10, ErrorCode::PoolFull);
     require!(
         lend_auction
@@ -48,81 +80,244 @@ pub fn process_submit_bid(
         min_rate,
         lend_auction.shard_count,
     );
+
+    // Record shard and rent payer on pools created by this instruction
+    if shard_pool.payer == Pubkey::default() {
+        shard_pool.shard_id = shard_id;
+        shard_pool.payer = authority.key();
+    }
+    if loan_pool.payer == Pubkey::default() {
+        loan_pool.shard_id = shard_id;
+        loan_pool.payer = authority.key();
+    }
     require_eq!(shard_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
     require_eq!(loan_pool.shard_id, shard_id, ErrorCode::ShardMismatch);
+    if bidder_positions.owner == Pubkey::default() {
+        bidder_positions.owner = bidder.key();
+    }
+
+    let bid = Bid {
+        lender: bidder.key(),
+        amount,
+        min_rate,
+        slot: Clock::get()?.slot,
+        token_mint: ctx.accounts.token_mint.key(),
+        duration_slots,
+        early_repayment,
+        delegate: if bidder.key() == authority.key() {
+            None
+        } else {
+            ctx.accounts
+                .delegate
+                .as_ref()
+                .map(|delegate| delegate.key())
+        },
+    };
+
+    // Match bid with asks atomically
+    let outcome = match_bid(&bid, &mut shard_pool.asks, self_trade_prevention)?;
+    if outcome.taker_cancelled {
+        emit!(SelfTradePrevented {
+            owner: bid.lender,
+            side: OrderSide::Bid,
+            resting: false,
+            amount,
+            collateral: 0,
+            shard_id,
+            token_mint: bid.token_mint,
+        });
+        return Ok(());
+    }
+
+    // Charge the delegate only for a bid that is funded, whether it matches or rests
+    if let (Some(_), Some(delegate)) = (bid.delegate, ctx.accounts.delegate.as_mut()) {
+        charge_delegate(delegate, &bid.spend())?;
+    }
 
     // Transfer loan tokens to vault
     token::transfer(
-        CpiContext::new(
+        CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
             Transfer {
                 from: ctx.accounts.bidder_token_account.to_account_info(),
                 to: ctx.accounts.vault_token_account.to_account_info(),
-                authority: bidder.to_account_info(),
+                authority: spend_authority(
+                    &bidder.key(),
+                    &authority.to_account_info(),
+                    &lend_auction.to_account_info(),
+                ),
             },
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
         ),
         amount,
     )?;
 
-    let bid = Bid {
-        lender: bidder.key(),
-        amount,
-        min_rate,
-        slot: Clock::get()?.slot,
-        token_mint: ctx.accounts.token_mint.key(),
-        duration_slots,
-    };
+    // Refund resting asks cancelled by self-trade prevention
+    for ask in outcome.cancelled {
+        let (Some(bidder_collateral_account), Some(vault_collateral_account)) = (
+            &ctx.accounts.bidder_collateral_account,
+            &ctx.accounts.vault_collateral_account,
+        ) else {
+            return err!(ErrorCode::MissingRefundAccount);
+        };
+        require_eq!(
+            bidder_collateral_account.mint,
+            ask.collateral_mint,
+            ErrorCode::InvalidTokenAccount
+        );
+        require_eq!(
+            vault_collateral_account.mint,
+            ask.collateral_mint,
+            ErrorCode::InvalidVaultAccount
+        );
 
-    // Match bid with asks atomically
-    let matches = match_bid(&bid, &mut shard_pool.asks)?;
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: vault_collateral_account.to_account_info(),
+                    to: bidder_collateral_account.to_account_info(),
+                    authority: lend_auction.to_account_info(),
+                },
+                &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+            ),
+            ask.collateral,
+        )?;
+        transfer_basket(
+            ctx.remaining_accounts,
+            &ask.basket,
+            &lend_auction.key(),
+            &ask.borrower,
+            &lend_auction.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+        )?;
+        if let Some(delegate) = ctx
+            .accounts
+            .delegate
+            .as_mut()
+            .filter(|delegate| ask.delegate == Some(delegate.key()))
+        {
+            refund_delegate(delegate, &ask.spend());
+        }
+
+        emit!(SelfTradePrevented {
+            owner: ask.borrower,
+            side: OrderSide::Ask,
+            resting: true,
+            amount: ask.amount,
+            collateral: ask.collateral,
+            shard_id,
+            token_mint: ask.token_mint,
+        });
+    }
+
+    let matches = outcome.matches;
     if !matches.is_empty() {
         let mut total_matched = 0;
         let mut loans = Vec::new();
+        let mut destinations = Vec::new();
+        let now = Clock::get()?.unix_timestamp;
 
         for (ask, rate) in matches {
             let loan_amount = cmp::min(bid.amount - total_matched, ask.amount);
+            let loan_collateral = ask
+                .collateral
+                .checked_mul(loan_amount)
+                .ok_or(ErrorCode::Overflow)?
+                .checked_div(ask.amount)
+                .ok_or(ErrorCode::Overflow)?;
             let loan = Loan {
+                loan_id: lend_auction
+                    .total_loans
+                    .checked_add(loans.len() as u64)
+                    .ok_or(ErrorCode::Overflow)?,
+                payer: authority.key(),
                 lender: bid.lender,
                 borrower: ask.borrower,
                 amount: loan_amount,
                 rate,
-                collateral: ask.collateral,
-                repaid: false,
+                collateral: loan_collateral,
+                status: LoanStatus::Active,
                 shard_id,
                 token_mint: bid.token_mint,
                 collateral_mint: ask.collateral_mint,
-                start_slot: Clock::get()?.slot,
-                duration_slots: bid.duration_slots,
+                start_ts: now,
+                maturity_ts: compute_maturity(now, bid.duration_slots)?,
+                accrued_interest: 0,
+                accrued_penalty: 0,
+                last_accrual_ts: now,
+                early_repayment: bid.early_repayment,
+                position_mint: None,
+                claimable: 0,
+                basket: split_basket(&ask.basket, loan_amount, ask.amount)?,
             };
 
-            // Validate collateral ratio
+            // Validate collateral ratio over the whole basket
             require_gte!(
-                loan.collateral,
-                loan.amount
-                    .checked_mul(15)
-                    .ok_or(ErrorCode::Overflow)?
-                    .checked_div(10)
-                    .ok_or(ErrorCode::Overflow)?,
+                collateral_value(
+                    ctx.remaining_accounts,
+                    &loan.collateral_mint,
+                    loan.collateral,
+                    &loan.basket,
+                )?,
+                required_collateral(&ctx.accounts.market, loan.amount)?,
                 ErrorCode::InsufficientCollateral
             );
 
             total_matched = total_matched
                 .checked_add(loan.amount)
                 .ok_or(ErrorCode::Overflow)?;
+
+            // Reinsert remaining ask amount with its unused collateral
+            if ask.amount > loan_amount {
+                require!(
+                    is_valid_remainder(&ctx.accounts.market, ask.amount - loan_amount),
+                    ErrorCode::OrderTooSmall
+                );
+                let mut remaining_ask = Ask {
+                    amount: ask.amount - loan_amount,
+                    collateral: ask.collateral - loan_collateral,
+                    ..ask.clone()
+                };
+                let collateral_config =
+                    load_collateral_config(ctx.remaining_accounts, &ask.collateral_mint)?;
+                validate_collateral_size(&collateral_config, remaining_ask.collateral)?;
+                for (component, taken) in remaining_ask.basket.iter_mut().zip(&loan.basket) {
+                    component.amount = component
+                        .amount
+                        .checked_sub(taken.amount)
+                        .ok_or(ErrorCode::Overflow)?;
+                }
+                insert_sorted_ask(shard_pool, remaining_ask);
+            }
             loans.push(loan);
+            destinations.push(proceeds_destination(
+                &ask.borrower,
+                &ask.token_mint,
+                ask.payout,
+            ));
         }
 
         // Require full match for atomicity
         require_eq!(total_matched, bid.amount, ErrorCode::PartialMatchNotAllowed);
 
-        // Process all transfers and store loans
-        for loan in &loans {
+        // Process all transfers and create one loan account per match
+        require!(
+            ctx.remaining_accounts.len() >= loans.len(),
+            ErrorCode::InvalidLoanAccount
+        );
+        for ((loan, loan_account), destination) in
+            loans.iter().zip(ctx.remaining_accounts).zip(&destinations)
+        {
+            let proceeds_account =
+                load_proceeds_account(ctx.remaining_accounts, destination, &loan.token_mint)?;
             token::transfer(
                 CpiContext::new_with_signer(
                     ctx.accounts.token_program.to_account_info(),
                     Transfer {
                         from: ctx.accounts.vault_token_account.to_account_info(),
-                        to: ctx.accounts.borrower_token_account.to_account_info(),
+                        to: proceeds_account.clone(),
                         authority: lend_auction.to_account_info(),
                     },
                     &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
@@ -130,13 +325,35 @@ pub fn process_submit_bid(
                 loan.amount,
             )?;
 
+            create_loan_account(
+                loan_account,
+                &authority.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                loan,
+            )?;
+            open_loan_position(bidder_positions, loan)?;
+            let mut borrower_positions =
+                load_user_positions(ctx.remaining_accounts, &loan.borrower)?;
+            open_loan_position(&mut borrower_positions, loan)?;
+            sync_order_shards(&mut borrower_positions, shard_pool)?;
+            fit_user_positions(
+                &borrower_positions,
+                &authority.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+            )?;
+            borrower_positions.exit(&crate::ID)?;
+
             lend_auction.total_loans = lend_auction
                 .total_loans
                 .checked_add(1)
                 .ok_or(ErrorCode::Overflow)?;
-            loan_pool.loans.push(loan.clone());
+            loan_pool.active_loans = loan_pool
+                .active_loans
+                .checked_add(1)
+                .ok_or(ErrorCode::Overflow)?;
 
             emit!(LoanIssued {
+                loan_id: loan.loan_id,
                 lender: loan.lender,
                 borrower: loan.borrower,
                 amount: loan.amount,
@@ -157,41 +374,72 @@ pub fn process_submit_bid(
         });
     }
 
+    // Index the owner's resting order, or drop shards emptied by self-trade prevention
+    sync_order_shards(bidder_positions, shard_pool)?;
+    fit_user_positions(
+        bidder_positions,
+        &authority.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+    )?;
+
     Ok(())
 }
 
 #[derive(Accounts)]
-#[instruction(amount: u64, min_rate: u8, duration_slots: u64)]
+#[instruction(amount: u64, min_rate: u8, duration_slots: u64, early_repayment: EarlyRepayment, self_trade_prevention: SelfTradePrevention)]
 pub struct SubmitBid<'info> {
     #[account(mut, seeds = [b"lend_auction"], bump)]
     pub lend_auction: Box<Account<'info, LendAuction>>,
+    #[account(seeds = [b"market", token_mint.key().as_ref()], bump)]
+    pub market: Box<Account<'info, Market>>,
     #[account(
         init_if_needed,
-        payer = bidder,
-        space = 8 + 8 + 10 * (32 + 8 + 1 + 8 + 32 + 8) + 10 * (32 + 8 + 1 + 8 + 8 + 32),
+        payer = authority,
+        space = 8 + 8 + 32 + 4 + 10 * (32 + 8 + 1 + 8 + 32 + 8 + 9 + 33) + 4 + 10 * (32 + 8 + 1 + 8 + 8 + 32 + 32 + 33 + 4 + MAX_BASKET_COMPONENTS * CollateralComponent::SPACE + 33),
         seeds = [b"shard_pool", &compute_shard_id(&token_mint.key(), min_rate, lend_auction.shard_count).to_le_bytes()[..]], // Compute in function
         bump
     )]
     pub shard_pool: Box<Account<'info, ShardPool>>,
     #[account(
         init_if_needed,
-        payer = bidder,
-        space = 8 + 8 + 10 * (32 + 32 + 8 + 1 + 8 + 1 + 8 + 32 + 32 + 8 + 8),
+        payer = authority,
+        space = 8 + 8 + 32 + 8,
         seeds = [b"loan_pool", &compute_shard_id(&token_mint.key(), min_rate, lend_auction.shard_count).to_le_bytes()[..]], // Compute in function
         bump
     )]
     pub loan_pool: Box<Account<'info, LoanPool>>,
     #[account(mut)]
-    pub bidder: Signer<'info>,
+    pub authority: Signer<'info>,
+    /// CHECK: Order owner, validated against the signing authority or its delegate
+    pub bidder: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [b"delegate", bidder.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub delegate: Option<Account<'info, Delegate>>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = UserPositions::SPACE,
+        seeds = [b"user_positions", bidder.key().as_ref()],
+        bump
+    )]
+    pub bidder_positions: Box<Account<'info, UserPositions>>,
     #[account(mut, constraint = bidder_token_account.owner == bidder.key())]
     pub bidder_token_account: Box<Account<'info, TokenAccount>>,
-    #[account(mut)]
-    pub borrower_token_account: Box<Account<'info, TokenAccount>>,
     #[account(
         mut,
         constraint = vault_token_account.owner == lend_auction.key()
     )]
     pub vault_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut, constraint = bidder_collateral_account.owner == bidder.key())]
+    pub bidder_collateral_account: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = vault_collateral_account.owner == lend_auction.key()
+    )]
+    pub vault_collateral_account: Option<Box<Account<'info, TokenAccount>>>,
     pub token_mint: Box<Account<'info, Mint>>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
@@ -0,0 +1,91 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::spl_token::instruction::AuthorityType;
+use anchor_spl::token::{mint_to, set_authority, Mint, MintTo, SetAuthority, Token, TokenAccount};
+
+use crate::errors::ErrorCode;
+use crate::events::LoanTokenized;
+use crate::states::{LendAuction, Loan};
+
+/// Lender mints a supply-1 position token for a loan. From then on repayments, liquidation
+/// proceeds and default claims go to whoever holds the token.
+pub fn process_tokenize_loan(ctx: Context<TokenizeLoan>, loan_id: u64) -> Result<()> {
+    let loan = &mut ctx.accounts.loan;
+    let lend_auction = &ctx.accounts.lend_auction;
+
+    require!(
+        loan.position_mint.is_none(),
+        ErrorCode::LoanAlreadyTokenized
+    );
+    require_keys_eq!(
+        loan.lender,
+        ctx.accounts.lender.key(),
+        ErrorCode::Unauthorized
+    );
+
+    mint_to(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            MintTo {
+                mint: ctx.accounts.position_mint.to_account_info(),
+                to: ctx.accounts.lender_position_account.to_account_info(),
+                authority: lend_auction.to_account_info(),
+            },
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+        ),
+        1,
+    )?;
+
+    // Drop the mint authority so the supply stays fixed at one
+    set_authority(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            SetAuthority {
+                current_authority: lend_auction.to_account_info(),
+                account_or_mint: ctx.accounts.position_mint.to_account_info(),
+            },
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+        ),
+        AuthorityType::MintTokens,
+        None,
+    )?;
+
+    loan.position_mint = Some(ctx.accounts.position_mint.key());
+
+    emit!(LoanTokenized {
+        loan_id,
+        lender: loan.lender,
+        position_mint: ctx.accounts.position_mint.key(),
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct TokenizeLoan<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(mut, seeds = [b"loan", loan_id.to_le_bytes().as_ref()], bump)]
+    pub loan: Account<'info, Loan>,
+    #[account(
+        init,
+        payer = lender,
+        mint::decimals = 0,
+        mint::authority = lend_auction,
+        seeds = [b"position_mint", loan_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub position_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = lender,
+        associated_token::mint = position_mint,
+        associated_token::authority = lender
+    )]
+    pub lender_position_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub lender: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,69 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer, Token, TokenAccount, Transfer};
+
+use crate::errors::ErrorCode;
+use crate::events::BalanceWithdrawn;
+use crate::states::{LendAuction, MakerBalance};
+
+/// Withdraw unused tokens from a maker balance
+pub fn process_withdraw_balance(ctx: Context<WithdrawBalance>, amount: u64) -> Result<()> {
+    let lend_auction = &ctx.accounts.lend_auction;
+    let maker_balance = &mut ctx.accounts.maker_balance;
+
+    require!(amount > 0, ErrorCode::InvalidAmount);
+    require_gte!(maker_balance.amount, amount, ErrorCode::InsufficientBalance);
+    require_eq!(
+        ctx.accounts.owner_token_account.mint,
+        maker_balance.mint,
+        ErrorCode::InvalidTokenAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_account.mint,
+        maker_balance.mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.vault_account.to_account_info(),
+                to: ctx.accounts.owner_token_account.to_account_info(),
+                authority: lend_auction.to_account_info(),
+            },
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+        ),
+        amount,
+    )?;
+
+    maker_balance.amount -= amount;
+
+    emit!(BalanceWithdrawn {
+        owner: maker_balance.owner,
+        mint: maker_balance.mint,
+        amount,
+        balance: maker_balance.amount,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct WithdrawBalance<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(
+        mut,
+        seeds = [b"maker_balance", owner.key().as_ref(), maker_balance.mint.as_ref()],
+        bump
+    )]
+    pub maker_balance: Account<'info, MakerBalance>,
+    pub owner: Signer<'info>,
+    #[account(mut, constraint = owner_token_account.owner == owner.key())]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_account.owner == lend_auction.key()
+    )]
+    pub vault_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
@@ -0,0 +1,113 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{transfer, Token, TokenAccount, Transfer};
+
+use crate::{
+    errors::ErrorCode,
+    events::CollateralWithdrawn,
+    states::{LendAuction, Loan, LoanStatus, Market, UserPositions},
+    utils::{collateral_value, compute_health_factor, compute_repayment, release_collateral},
+};
+
+/// Borrower withdraws excess collateral while the loan stays at or above the market's
+/// initial collateral ratio, valued the same way as liquidation.
+/// Remaining accounts hold the collateral configs of the loan's mints when it has a basket.
+pub fn process_withdraw_collateral<'info>(
+    ctx: Context<'_, '_, 'info, 'info, WithdrawCollateral<'info>>,
+    loan_id: u64,
+    amount: u64,
+) -> Result<()> {
+    let loan = &mut ctx.accounts.loan;
+    let lend_auction = &ctx.accounts.lend_auction;
+
+    require!(amount > 0, ErrorCode::InvalidCollateral);
+    require!(
+        loan.status == LoanStatus::Active,
+        ErrorCode::InvalidLoanStatus
+    );
+    require_keys_eq!(
+        loan.borrower,
+        ctx.accounts.borrower.key(),
+        ErrorCode::Unauthorized
+    );
+    require_eq!(
+        ctx.accounts.borrower_collateral_account.mint,
+        loan.collateral_mint,
+        ErrorCode::InvalidTokenAccount
+    );
+    require_eq!(
+        ctx.accounts.vault_collateral_account.mint,
+        loan.collateral_mint,
+        ErrorCode::InvalidVaultAccount
+    );
+
+    let remaining_collateral = loan
+        .collateral
+        .checked_sub(amount)
+        .ok_or(ErrorCode::InsufficientCollateral)?;
+    let repayment = compute_repayment(loan, Clock::get()?.unix_timestamp)?;
+    let value = collateral_value(
+        ctx.remaining_accounts,
+        &loan.collateral_mint,
+        remaining_collateral,
+        &loan.basket,
+    )?;
+    let health_factor = compute_health_factor(value, repayment)?;
+    require_gte!(
+        health_factor,
+        ctx.accounts.market.initial_collateral_ratio as u64,
+        ErrorCode::InsufficientCollateral
+    );
+
+    transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.vault_collateral_account.to_account_info(),
+                to: ctx.accounts.borrower_collateral_account.to_account_info(),
+                authority: lend_auction.to_account_info(),
+            },
+            &[&[b"lend_auction", &[ctx.bumps.lend_auction]]],
+        ),
+        amount,
+    )?;
+
+    loan.collateral = remaining_collateral;
+    release_collateral(
+        &mut ctx.accounts.borrower_positions,
+        &loan.collateral_mint,
+        amount,
+    )?;
+
+    emit!(CollateralWithdrawn {
+        loan_id,
+        borrower: loan.borrower,
+        amount,
+        collateral: loan.collateral,
+        health_factor,
+        shard_id: loan.shard_id,
+        collateral_mint: loan.collateral_mint,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(loan_id: u64)]
+pub struct WithdrawCollateral<'info> {
+    #[account(seeds = [b"lend_auction"], bump)]
+    pub lend_auction: Account<'info, LendAuction>,
+    #[account(mut, seeds = [b"loan", loan_id.to_le_bytes().as_ref()], bump)]
+    pub loan: Account<'info, Loan>,
+    #[account(seeds = [b"market", loan.token_mint.as_ref()], bump)]
+    pub market: Account<'info, Market>,
+    #[account(mut, seeds = [b"user_positions", loan.borrower.as_ref()], bump)]
+    pub borrower_positions: Box<Account<'info, UserPositions>>,
+    pub borrower: Signer<'info>,
+    #[account(mut, constraint = borrower_collateral_account.owner == borrower.key())]
+    pub borrower_collateral_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = vault_collateral_account.owner == lend_auction.key()
+    )]
+    pub vault_collateral_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
@@ -5,23 +5,19 @@ use crate::errors::ErrorCode;
 use crate::events::FeesWithdrawn;
 use crate::states::LendAuction;
 
-/// Admin withdraws fees from a specific shard
-pub fn process_withdraw_fees(ctx: Context<WithdrawFees>, shard_id: u64, amount: u64) -> Result<()> {
+/// Admin withdraws collected fees from the fee vault of one mint
+pub fn process_withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
     let lend_auction = &ctx.accounts.lend_auction;
     let admin = &ctx.accounts.admin;
 
     require_eq!(lend_auction.admin, *admin.key, ErrorCode::Unauthorized);
-    require!(shard_id < lend_auction.shard_count, ErrorCode::InvalidShard);
     require!(amount > 0, ErrorCode::InvalidAmount);
     require!(
-        lend_auction.supported_tokens.contains(&ctx.accounts.token_mint.key()),
+        lend_auction
+            .supported_tokens
+            .contains(&ctx.accounts.token_mint.key()),
         ErrorCode::UnsupportedToken
     );
-    require_eq!(
-        ctx.accounts.fee_vault.mint,
-        ctx.accounts.token_mint.key(),
-        ErrorCode::InvalidVaultAccount
-    );
     require_gte!(
         ctx.accounts.fee_vault.amount,
         amount,
@@ -43,7 +39,6 @@ pub fn process_withdraw_fees(ctx: Context<WithdrawFees>, shard_id: u64, amount:
 
     emit!(FeesWithdrawn {
         admin: lend_auction.admin,
-        shard_id,
         amount,
         token_mint: ctx.accounts.token_mint.key(),
     });
@@ -51,16 +46,12 @@ pub fn process_withdraw_fees(ctx: Context<WithdrawFees>, shard_id: u64, amount:
 }
 
 #[derive(Accounts)]
-#[instruction(shard_id: u64, amount: u64)]
 pub struct WithdrawFees<'info> {
     #[account(mut, seeds = [b"lend_auction"], bump)]
     pub lend_auction: Account<'info, LendAuction>,
     #[account(mut)]
     pub admin: Signer<'info>,
-    #[account(
-        mut,
-        constraint = fee_vault.owner == lend_auction.key()
-    )]
+    #[account(mut, seeds = [b"fee_vault", token_mint.key().as_ref()], bump)]
     pub fee_vault: Account<'info, TokenAccount>,
     #[account(mut, constraint = admin_token_account.owner == admin.key())]
     pub admin_token_account: Account<'info, TokenAccount>,
@@ -4,15 +4,21 @@ use anchor_lang::prelude::*;
 
 mod instructions;
 use instructions::*;
+use states::{
+    BestRates, CollateralComponent, Depth, EarlyRepayment, LoanInfo, MatchQuote, OrderSide,
+    SelfTradePrevention, SignedOrder, SpendCap,
+};
 
 mod errors;
 mod events;
+mod math;
 mod states;
 mod utils;
 
 declare_id!("EDvhvdnYVX2JsuAXvJXvBN3jd79ceNYMMnw11JSvzCPo");
 
-pub const RAYDIUM_AMM_PROGRAM: &str = "RAYDIUM_AMM_PROGRAM_ID";
+/// Raydium AMM v4 program that collateral swaps are routed through
+pub const RAYDIUM_AMM_PROGRAM: Pubkey = pubkey!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
 
 #[program]
 pub mod contract {
@@ -26,41 +32,257 @@ pub mod contract {
         process_initialize(ctx, shard_count, supported_tokens)
     }
 
-    pub fn submit_bid(
-        ctx: Context<SubmitBid>,
+    pub fn configure_market(
+        ctx: Context<ConfigureMarket>,
+        min_amount: u64,
+        max_amount: u64,
+        grace_period: i64,
+        penalty_rate: u8,
+        initial_collateral_ratio: u16,
+    ) -> Result<()> {
+        process_configure_market(
+            ctx,
+            min_amount,
+            max_amount,
+            grace_period,
+            penalty_rate,
+            initial_collateral_ratio,
+        )
+    }
+
+    pub fn configure_collateral(
+        ctx: Context<ConfigureCollateral>,
+        haircut_bps: u16,
+        min_collateral: u64,
+        max_collateral: u64,
+    ) -> Result<()> {
+        process_configure_collateral(ctx, haircut_bps, min_collateral, max_collateral)
+    }
+
+    pub fn create_fee_vault(ctx: Context<CreateFeeVault>) -> Result<()> {
+        process_create_fee_vault(ctx)
+    }
+
+    pub fn submit_bid<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SubmitBid<'info>>,
         amount: u64,
         min_rate: u8,
         duration_slots: u64,
+        early_repayment: EarlyRepayment,
+        self_trade_prevention: SelfTradePrevention,
     ) -> Result<()> {
-        process_submit_bid(ctx, amount, min_rate, duration_slots)
+        process_submit_bid(
+            ctx,
+            amount,
+            min_rate,
+            duration_slots,
+            early_repayment,
+            self_trade_prevention,
+        )
     }
 
-    pub fn submit_ask(
-        ctx: Context<SubmitAsk>,
+    pub fn submit_ask<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SubmitAsk<'info>>,
         amount: u64,
         max_rate: u8,
         collateral: u64,
+        basket: Vec<CollateralComponent>,
+        self_trade_prevention: SelfTradePrevention,
+    ) -> Result<()> {
+        process_submit_ask(
+            ctx,
+            amount,
+            max_rate,
+            collateral,
+            basket,
+            self_trade_prevention,
+        )
+    }
+
+    pub fn repay<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Repay<'info>>,
+        loan_id: u64,
+    ) -> Result<()> {
+        process_repay(ctx, loan_id)
+    }
+
+    pub fn repay_for<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RepayFor<'info>>,
+        loan_id: u64,
+    ) -> Result<()> {
+        process_repay_for(ctx, loan_id)
+    }
+
+    pub fn repay_with_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RepayWithCollateral<'info>>,
+        loan_id: u64,
+        max_collateral_in: u64,
+    ) -> Result<()> {
+        process_repay_with_collateral(ctx, loan_id, max_collateral_in)
+    }
+
+    pub fn repay_partial<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RepayPartial<'info>>,
+        loan_id: u64,
+        amount: u64,
+        release_collateral: bool,
+    ) -> Result<()> {
+        process_repay_partial(ctx, loan_id, amount, release_collateral)
+    }
+
+    pub fn add_collateral(ctx: Context<AddCollateral>, loan_id: u64, amount: u64) -> Result<()> {
+        process_add_collateral(ctx, loan_id, amount)
+    }
+
+    pub fn withdraw_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawCollateral<'info>>,
+        loan_id: u64,
+        amount: u64,
     ) -> Result<()> {
-        process_submit_ask(ctx, amount, max_rate, collateral)
+        process_withdraw_collateral(ctx, loan_id, amount)
     }
 
-    pub fn repay(ctx: Context<Repay>, loan_idx: u64, rate: u8) -> Result<()> {
-        process_repay(ctx, loan_idx, rate)
+    pub fn refinance<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Refinance<'info>>,
+        loan_id: u64,
+        shard_id: u64,
+        bid_idx: u64,
+        max_rate: u8,
+    ) -> Result<()> {
+        process_refinance(ctx, loan_id, shard_id, bid_idx, max_rate)
+    }
+
+    pub fn extend_loan(
+        ctx: Context<ExtendLoan>,
+        loan_id: u64,
+        new_maturity_ts: i64,
+        new_rate: u8,
+    ) -> Result<()> {
+        process_extend_loan(ctx, loan_id, new_maturity_ts, new_rate)
     }
 
-    pub fn liquidate(
-        ctx: Context<Liquidate>,
-        loan_idx: u64,
+    pub fn tokenize_loan(ctx: Context<TokenizeLoan>, loan_id: u64) -> Result<()> {
+        process_tokenize_loan(ctx, loan_id)
+    }
+
+    pub fn liquidate<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Liquidate<'info>>,
+        loan_id: u64,
+        component: Option<u8>,
         minimum_amount_out: u64,
     ) -> Result<()> {
-        process_liquidate(ctx, loan_idx, minimum_amount_out)
+        process_liquidate(ctx, loan_id, component, minimum_amount_out)
+    }
+
+    pub fn approve_delegate(
+        ctx: Context<ApproveDelegate>,
+        delegate: Pubkey,
+        permissions: u8,
+        spend_caps: Vec<SpendCap>,
+        expiry_slot: u64,
+    ) -> Result<()> {
+        process_approve_delegate(ctx, delegate, permissions, spend_caps, expiry_slot)
+    }
+
+    pub fn revoke_delegate(ctx: Context<RevokeDelegate>, delegate: Pubkey) -> Result<()> {
+        process_revoke_delegate(ctx, delegate)
+    }
+
+    pub fn cancel_order<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CancelOrder<'info>>,
+        shard_id: u64,
+        side: OrderSide,
+        order_idx: u64,
+    ) -> Result<()> {
+        process_cancel_order(ctx, shard_id, side, order_idx)
+    }
+
+    pub fn deposit_balance(ctx: Context<DepositBalance>, amount: u64) -> Result<()> {
+        process_deposit_balance(ctx, amount)
+    }
+
+    pub fn withdraw_balance(ctx: Context<WithdrawBalance>, amount: u64) -> Result<()> {
+        process_withdraw_balance(ctx, amount)
+    }
+
+    pub fn cancel_nonces(ctx: Context<CancelNonces>, min_nonce: u64) -> Result<()> {
+        process_cancel_nonces(ctx, min_nonce)
+    }
+
+    pub fn settle_signed_order(ctx: Context<SettleSignedOrder>, order: SignedOrder) -> Result<()> {
+        process_settle_signed_order(ctx, order)
+    }
+
+    pub fn close_empty_pools(ctx: Context<CloseEmptyPools>, shard_id: u64) -> Result<()> {
+        process_close_empty_pools(ctx, shard_id)
+    }
+
+    pub fn mark_defaulted(ctx: Context<MarkDefaulted>, loan_id: u64) -> Result<()> {
+        process_mark_defaulted(ctx, loan_id)
+    }
+
+    pub fn claim_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimCollateral<'info>>,
+        loan_id: u64,
+    ) -> Result<()> {
+        process_claim_collateral(ctx, loan_id)
+    }
+
+    pub fn claim(ctx: Context<Claim>, loan_id: u64) -> Result<()> {
+        process_claim(ctx, loan_id)
+    }
+
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+        process_withdraw_fees(ctx, amount)
+    }
+
+    pub fn match_orders<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MatchOrders<'info>>,
+        shard_id: u64,
+        max_matches: u8,
+    ) -> Result<()> {
+        process_match_orders(ctx, shard_id, max_matches)
+    }
+
+    pub fn get_best_rates(
+        ctx: Context<GetBestRates>,
+        shard_id: u64,
+        token_mint: Pubkey,
+    ) -> Result<BestRates> {
+        process_get_best_rates(ctx, shard_id, token_mint)
     }
 
-    pub fn withdraw_fees(ctx: Context<WithdrawFees>, shard_id: u64, amount: u64) -> Result<()> {
-        process_withdraw_fees(ctx, shard_id, amount)
+    pub fn get_depth(
+        ctx: Context<GetDepth>,
+        shard_id: u64,
+        token_mint: Pubkey,
+        levels: u8,
+    ) -> Result<Depth> {
+        process_get_depth(ctx, shard_id, token_mint, levels)
     }
 
-    pub fn cleanup(ctx: Context<Cleanup>, shard_id: u64) -> Result<()> {
+    pub fn quote_match(
+        ctx: Context<QuoteMatch>,
+        shard_id: u64,
+        side: OrderSide,
+        token_mint: Pubkey,
+        amount: u64,
+        rate: u8,
+    ) -> Result<MatchQuote> {
+        process_quote_match(ctx, shard_id, side, token_mint, amount, rate)
+    }
+
+    pub fn get_loan<'info>(
+        ctx: Context<'_, '_, 'info, 'info, GetLoan<'info>>,
+        loan_id: u64,
+    ) -> Result<LoanInfo> {
+        process_get_loan(ctx, loan_id)
+    }
+
+    pub fn cleanup<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Cleanup<'info>>,
+        shard_id: u64,
+    ) -> Result<()> {
         process_cleanup(ctx, shard_id)
     }
 }
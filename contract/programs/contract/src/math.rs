@@ -0,0 +1,127 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::ErrorCode;
+
+/// Fixed-point scale: 1.0 is represented as 1e18
+pub const WAD: u128 = 1_000_000_000_000_000_000;
+
+/// Day-count basis: Actual/365 Fixed, elapsed wall-clock seconds over a 365-day year
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Nominal slot length used to turn order durations in slots into loan maturities
+pub const DEFAULT_MS_PER_SLOT: u64 = 400;
+
+/// Divide, rounding up
+fn div_up(numerator: u128, denominator: u128) -> Result<u128> {
+    require!(denominator > 0, ErrorCode::Overflow);
+    Ok(numerator.div_ceil(denominator))
+}
+
+/// Fraction of principal accrued as interest, as a WAD, after `elapsed_seconds` at a
+/// simple annual rate of `apr` percent. Accrues every second and keeps accruing past
+/// maturity. Rounded up, in the lender's favor.
+pub fn accrued_interest_wad(apr: u8, elapsed_seconds: u64) -> Result<u128> {
+    div_up(
+        (apr as u128)
+            .checked_mul(elapsed_seconds as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_mul(WAD)
+            .ok_or(ErrorCode::Overflow)?,
+        100 * SECONDS_PER_YEAR as u128,
+    )
+}
+
+/// Maturity timestamp of a loan starting at `start_ts` for an order duration in slots
+pub fn compute_maturity(start_ts: i64, duration_slots: u64) -> Result<i64> {
+    let duration_seconds = duration_slots
+        .checked_mul(DEFAULT_MS_PER_SLOT)
+        .ok_or(ErrorCode::Overflow)?
+        / 1000;
+    Ok(start_ts
+        .checked_add(i64::try_from(duration_seconds).map_err(|_| ErrorCode::Overflow)?)
+        .ok_or(ErrorCode::Overflow)?)
+}
+
+/// Apply a WAD fraction to a token amount, rounding up to whole base units.
+/// Used for every amount owed to the lender.
+pub fn apply_wad_up(amount: u64, fraction: u128) -> Result<u64> {
+    let scaled = div_up(
+        (amount as u128)
+            .checked_mul(fraction)
+            .ok_or(ErrorCode::Overflow)?,
+        WAD,
+    )?;
+    Ok(u64::try_from(scaled).map_err(|_| ErrorCode::Overflow)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accrued_interest_rounds_up_in_the_lenders_favor() {
+        // 1% APR for one second is 317_097_919.83... WAD
+        assert_eq!(accrued_interest_wad(1, 1).unwrap(), 317_097_920);
+        assert_eq!(accrued_interest_wad(0, 1_000).unwrap(), 0);
+        assert_eq!(accrued_interest_wad(1, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn apply_wad_up_rounds_up_to_whole_base_units() {
+        assert_eq!(apply_wad_up(1_000, WAD / 2).unwrap(), 500);
+        assert_eq!(apply_wad_up(3, WAD / 2).unwrap(), 2);
+        assert_eq!(apply_wad_up(1_000, 0).unwrap(), 0);
+
+        // Even a single base unit lent for a second owes interest rather than truncating to zero
+        assert_eq!(
+            apply_wad_up(1, accrued_interest_wad(1, 1).unwrap()).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn accrual_fails_rather_than_wrapping_at_u64_max() {
+        assert_eq!(apply_wad_up(u64::MAX, WAD).unwrap(), u64::MAX);
+        assert!(apply_wad_up(u64::MAX, WAD + 1).is_err());
+        assert!(apply_wad_up(u64::MAX, u128::MAX).is_err());
+        assert!(accrued_interest_wad(u8::MAX, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn interest_accrues_actual_365_on_elapsed_seconds() {
+        let day = 24 * 60 * 60;
+
+        // A full 365-day year accrues exactly the APR
+        assert_eq!(
+            accrued_interest_wad(10, SECONDS_PER_YEAR).unwrap(),
+            WAD / 10
+        );
+        assert_eq!(
+            apply_wad_up(
+                1_000_000,
+                accrued_interest_wad(10, SECONDS_PER_YEAR).unwrap()
+            )
+            .unwrap(),
+            100_000
+        );
+        // 30 days at 12% APR is 1_000_000 * 12% * 30 / 365 = 9_863.01..., rounded up
+        assert_eq!(
+            apply_wad_up(1_000_000, accrued_interest_wad(12, 30 * day).unwrap()).unwrap(),
+            9_864
+        );
+        // A 366-day span accrues past the APR rather than using a 366-day year
+        assert_eq!(
+            apply_wad_up(1_000_000, accrued_interest_wad(10, 366 * day).unwrap()).unwrap(),
+            100_274
+        );
+    }
+
+    #[test]
+    fn maturity_converts_slots_at_the_nominal_slot_length() {
+        assert_eq!(
+            compute_maturity(1_700_000_000, 1_000).unwrap(),
+            1_700_000_400
+        );
+        assert!(compute_maturity(i64::MAX, 1_000).is_err());
+    }
+}
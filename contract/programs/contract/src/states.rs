@@ -8,9 +8,87 @@ pub struct LendAuction {
     pub supported_tokens: Vec<Pubkey>,
 }
 
+#[account]
+pub struct Market {
+    pub token_mint: Pubkey,
+    pub min_amount: u64,
+    pub max_amount: u64,
+    /// Seconds after maturity before an unpaid loan may be marked defaulted
+    pub grace_period: i64,
+    /// Extra APR in percent charged on the balance owed at maturity while overdue
+    pub penalty_rate: u8,
+    /// Collateral to owed-value ratio in percent required to open a loan or withdraw collateral
+    pub initial_collateral_ratio: u16,
+}
+
+/// Per-mint valuation of collateral posted in a basket
+#[account]
+pub struct CollateralConfig {
+    pub mint: Pubkey,
+    /// Discount applied to the mint's amount when valuing a basket, in basis points
+    pub haircut_bps: u16,
+    /// Mint decimals, used to convert basket amounts into the primary collateral's base units
+    pub decimals: u8,
+    /// Order collateral limits when the mint is posted as primary collateral, in its base units
+    pub min_collateral: u64,
+    pub max_collateral: u64,
+}
+
+/// Delegate may place orders on the owner's behalf
+pub const DELEGATE_PLACE_ORDERS: u8 = 1 << 0;
+/// Delegate may cancel the owner's resting orders
+pub const DELEGATE_CANCEL_ORDERS: u8 = 1 << 1;
+/// Delegate may repay the owner's loans
+pub const DELEGATE_REPAY: u8 = 1 << 2;
+
+/// Maximum mints a delegate may be allowed to spend
+pub const MAX_DELEGATE_MINTS: usize = 4;
+
+#[account]
+pub struct Delegate {
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    pub permissions: u8,
+    /// Per-mint limits on the owner's tokens the delegate may commit; other mints may not be spent
+    pub spend_caps: Vec<SpendCap>,
+    pub expiry_slot: u64,
+}
+
+impl Delegate {
+    pub const SPACE: usize = 8 + 32 + 32 + 1 + 4 + MAX_DELEGATE_MINTS * SpendCap::SPACE + 8;
+}
+
+/// Amount of one mint a delegate may spend, in base units, and how much it has spent
+#[derive(Clone, Copy, PartialEq, Eq, AnchorSerialize, AnchorDeserialize)]
+pub struct SpendCap {
+    pub mint: Pubkey,
+    pub cap: u64,
+    pub spent: u64,
+}
+
+impl SpendCap {
+    pub const SPACE: usize = 32 + 8 + 8;
+}
+
+#[account]
+pub struct MakerBalance {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    /// Signed orders funded by this balance with a lower nonce are revoked
+    pub min_nonce: u64,
+}
+
+/// Marks one signed order nonce of a maker balance as filled
+#[account]
+pub struct SignedOrderFill {
+    pub filled: bool,
+}
+
 #[account]
 pub struct ShardPool {
     pub shard_id: u64,
+    pub payer: Pubkey,
     pub bids: Vec<Bid>,
     pub asks: Vec<Ask>,
 }
@@ -18,22 +96,129 @@ pub struct ShardPool {
 #[account]
 pub struct LoanPool {
     pub shard_id: u64,
-    pub loans: Vec<Loan>,
+    pub payer: Pubkey,
+    pub active_loans: u64,
 }
 
-#[derive(Clone, AnchorSerialize, AnchorDeserialize)]
+#[account]
 pub struct Loan {
+    pub loan_id: u64,
+    pub payer: Pubkey,
+    /// Originating lender; once tokenized, the holder of `position_mint` owns the lender side
     pub lender: Pubkey,
     pub borrower: Pubkey,
     pub amount: u64,
+    /// Simple annual rate in percent, accrued Actual/365 on elapsed seconds
     pub rate: u8,
     pub collateral: u64,
-    pub repaid: bool,
+    pub status: LoanStatus,
     pub shard_id: u64,
     pub token_mint: Pubkey,
     pub collateral_mint: Pubkey,
-    pub start_slot: u64,
-    pub duration_slots: u64,
+    pub start_ts: i64,
+    pub maturity_ts: i64,
+    /// Interest accrued but unpaid as of `last_accrual_ts`
+    pub accrued_interest: u64,
+    /// Late penalty accrued but unpaid, kept apart from interest so penalty never accrues on it
+    pub accrued_penalty: u64,
+    pub last_accrual_ts: i64,
+    pub early_repayment: EarlyRepayment,
+    pub position_mint: Option<Pubkey>,
+    /// Repayments held in the vault until the lender side claims them
+    pub claimable: u64,
+    /// Collateral posted in other mints alongside `collateral`
+    pub basket: Vec<CollateralComponent>,
+}
+
+impl Loan {
+    pub const SPACE: usize = 8
+        + 8
+        + 32
+        + 32
+        + 32
+        + 8
+        + 1
+        + 8
+        + 1
+        + 8
+        + 32
+        + 32
+        + 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + 9
+        + 33
+        + 8
+        + 4
+        + MAX_BASKET_COMPONENTS * CollateralComponent::SPACE;
+
+    /// Whether the borrower still owes on this loan
+    pub fn is_open(&self) -> bool {
+        matches!(self.status, LoanStatus::Active | LoanStatus::Defaulted)
+    }
+}
+
+/// Per-wallet index of resting orders and open loans.
+/// The account is resized as entries are added, so the index is unbounded.
+#[account]
+pub struct UserPositions {
+    pub owner: Pubkey,
+    pub bid_shards: Vec<u64>,
+    pub ask_shards: Vec<u64>,
+    pub loans: Vec<u64>,
+    /// Totals over open loans, one entry per mint the wallet has lent, borrowed or posted
+    pub balances: Vec<PositionBalance>,
+}
+
+impl UserPositions {
+    /// Space of an empty index
+    pub const SPACE: usize = 8 + 32 + 4 + 4 + 4 + 4;
+}
+
+/// A wallet's open-loan totals in one mint, in its base units
+#[derive(Clone, Copy, PartialEq, Eq, AnchorSerialize, AnchorDeserialize)]
+pub struct PositionBalance {
+    pub mint: Pubkey,
+    pub supplied: u64,
+    pub borrowed: u64,
+    pub collateral_posted: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, AnchorSerialize, AnchorDeserialize)]
+pub enum LoanStatus {
+    Active,
+    Defaulted,
+    /// Paid off; closed once the lender claims the balance
+    Repaid,
+    /// Liquidated; closed once the lender claims the balance
+    Liquidated,
+}
+
+/// Maximum collateral mints posted alongside an order's or loan's primary collateral
+pub const MAX_BASKET_COMPONENTS: usize = 4;
+
+/// Collateral of one mint held in the lend auction's associated token account for that mint
+#[derive(Clone, Copy, PartialEq, Eq, AnchorSerialize, AnchorDeserialize)]
+pub struct CollateralComponent {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+impl CollateralComponent {
+    pub const SPACE: usize = 32 + 8;
+}
+
+/// Interest a lender is owed when a loan is repaid before maturity
+#[derive(Clone, Copy, PartialEq, Eq, AnchorSerialize, AnchorDeserialize)]
+pub enum EarlyRepayment {
+    /// Interest through maturity is always owed
+    FullTerm,
+    /// Interest for at least this many slots after origination is owed
+    MinimumSlots(u64),
+    /// Interest accrues only for the time the loan was outstanding
+    ProRata,
 }
 
 #[derive(Clone, AnchorSerialize, AnchorDeserialize)]
@@ -44,6 +229,16 @@ pub struct Bid {
     pub slot: u64,
     pub token_mint: Pubkey,
     pub duration_slots: u64,
+    pub early_repayment: EarlyRepayment,
+    /// Delegate account that placed the order, whose spend cap is refunded when it is cancelled
+    pub delegate: Option<Pubkey>,
+}
+
+impl Bid {
+    /// Deposit the resting order holds, per mint
+    pub fn spend(&self) -> Vec<(Pubkey, u64)> {
+        vec![(self.token_mint, self.amount)]
+    }
 }
 
 #[derive(Clone, AnchorSerialize, AnchorDeserialize)]
@@ -55,4 +250,122 @@ pub struct Ask {
     pub slot: u64,
     pub token_mint: Pubkey,
     pub collateral_mint: Pubkey,
+    /// Token account receiving loan proceeds; the borrower's associated token account when unset
+    pub payout: Option<Pubkey>,
+    /// Collateral posted in other mints alongside `collateral`
+    pub basket: Vec<CollateralComponent>,
+    /// Delegate account that placed the order, whose spend cap is refunded when it is cancelled
+    pub delegate: Option<Pubkey>,
+}
+
+impl Ask {
+    /// Deposit the resting order holds, per mint
+    pub fn spend(&self) -> Vec<(Pubkey, u64)> {
+        self.basket
+            .iter()
+            .map(|component| (component.mint, component.amount))
+            .chain([(self.collateral_mint, self.collateral)])
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, AnchorSerialize, AnchorDeserialize)]
+pub enum OrderSide {
+    Bid,
+    Ask,
+}
+
+/// Prefix of every signed order message, ahead of the program id and the Borsh-encoded order
+pub const SIGNED_ORDER_DOMAIN: &[u8] = b"lend_auction:signed_order";
+
+/// Maker order signed off chain; see `SignedOrder::message` for the signed bytes
+#[derive(Clone, AnchorSerialize, AnchorDeserialize)]
+pub struct SignedOrder {
+    pub maker: Pubkey,
+    pub side: OrderSide,
+    pub token_mint: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub amount: u64,
+    pub rate: u8,
+    pub collateral: u64,
+    pub duration_slots: u64,
+    pub early_repayment: EarlyRepayment,
+    pub nonce: u64,
+    pub expiry_slot: u64,
+}
+
+impl SignedOrder {
+    /// Bytes the maker signs, bound to this program so the signature cannot be replayed elsewhere
+    pub fn message(&self) -> Result<Vec<u8>> {
+        let mut message = SIGNED_ORDER_DOMAIN.to_vec();
+        message.extend_from_slice(crate::ID.as_ref());
+        message.extend_from_slice(&self.try_to_vec()?);
+        Ok(message)
+    }
+
+    /// Mint of the maker's pre-deposited balance that funds this order
+    pub fn funding_mint(&self) -> Pubkey {
+        match self.side {
+            OrderSide::Bid => self.token_mint,
+            OrderSide::Ask => self.collateral_mint,
+        }
+    }
+
+    /// Amount drawn from the maker's pre-deposited balance
+    pub fn funding_amount(&self) -> u64 {
+        match self.side {
+            OrderSide::Bid => self.amount,
+            OrderSide::Ask => self.collateral,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, AnchorSerialize, AnchorDeserialize)]
+pub enum SelfTradePrevention {
+    CancelResting,
+    CancelTaker,
+    Skip,
+}
+
+#[derive(Clone, AnchorSerialize, AnchorDeserialize)]
+pub struct BestRates {
+    pub shard_id: u64,
+    pub token_mint: Pubkey,
+    pub best_bid_rate: Option<u8>,
+    pub best_bid_amount: u64,
+    pub best_ask_rate: Option<u8>,
+    pub best_ask_amount: u64,
+}
+
+#[derive(Clone, AnchorSerialize, AnchorDeserialize)]
+pub struct DepthLevel {
+    pub rate: u8,
+    pub amount: u64,
+    pub orders: u32,
+}
+
+#[derive(Clone, AnchorSerialize, AnchorDeserialize)]
+pub struct Depth {
+    pub shard_id: u64,
+    pub token_mint: Pubkey,
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>,
+}
+
+#[derive(Clone, AnchorSerialize, AnchorDeserialize)]
+pub struct MatchQuote {
+    pub side: OrderSide,
+    pub requested_amount: u64,
+    pub matched_amount: u64,
+    pub fills: u32,
+    pub average_rate: u8,
+    pub fully_matched: bool,
+}
+
+#[derive(Clone, AnchorSerialize, AnchorDeserialize)]
+pub struct LoanInfo {
+    pub loan: Loan,
+    pub repayment_due: u64,
+    pub penalty_due: u64,
+    pub health_factor: u64,
 }
@@ -1,10 +1,23 @@
 use std::cmp;
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
 use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::system_program::{
+    allocate, assign, create_account, transfer as system_transfer, Allocate, Assign, CreateAccount,
+    Transfer as SystemTransfer,
+};
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::token::{transfer, TokenAccount, Transfer};
 
 use crate::errors::ErrorCode;
-use crate::states::{Ask, Bid, ShardPool};
+use crate::math::{accrued_interest_wad, apply_wad_up, compute_maturity};
+use crate::states::{
+    Ask, Bid, CollateralComponent, CollateralConfig, Delegate, DepthLevel, EarlyRepayment, Loan,
+    LoanStatus, Market, PositionBalance, SelfTradePrevention, ShardPool, UserPositions,
+    MAX_BASKET_COMPONENTS,
+};
 
 /// Compute shard ID based on token_mint and rate
 pub fn compute_shard_id(token_mint: &Pubkey, rate: u8, shard_count: u64) -> u64 {
@@ -18,91 +31,944 @@ pub fn compute_shard_id(token_mint: &Pubkey, rate: u8, shard_count: u64) -> u64
     u64::from_le_bytes(shard_bytes) % shard_count
 }
 
-/// Match a bid against sorted asks atomically, with a 5% rate difference limit
-pub fn match_bid(bid: &Bid, asks: &mut Vec<Ask>) -> Result<Vec<(Ask, u8)>> {
+/// Result of matching a taker order against the opposite side of a book
+pub struct MatchOutcome<T> {
+    pub matches: Vec<(T, u8)>,
+    pub cancelled: Vec<T>,
+    pub taker_cancelled: bool,
+}
+
+impl<T> MatchOutcome<T> {
+    fn taker_cancelled() -> Self {
+        Self {
+            matches: Vec::new(),
+            cancelled: Vec::new(),
+            taker_cancelled: true,
+        }
+    }
+}
+
+/// Check whether a bid and an ask cross, with a 5% rate difference limit
+pub fn orders_cross(bid: &Bid, ask: &Ask) -> bool {
+    ask.token_mint == bid.token_mint
+        && ask.max_rate >= bid.min_rate
+        && ask.max_rate.abs_diff(bid.min_rate) <= 5
+}
+
+/// Rate a crossing pair trades at: the midpoint of the two limits, capped at the ask's maximum
+pub fn match_rate(bid: &Bid, ask: &Ask) -> u8 {
+    let midpoint = (bid.min_rate as u16 + ask.max_rate as u16) / 2;
+    cmp::min(midpoint as u8, ask.max_rate)
+}
+
+/// Crank reward withheld from matched loan proceeds, in basis points of the loan amount
+pub const CRANK_REWARD_BPS: u64 = 5;
+
+/// Split a matched loan amount into the borrower's proceeds and the cranker's reward
+pub fn split_crank_reward(amount: u64) -> Result<(u64, u64)> {
+    let reward = (amount as u128)
+        .checked_mul(CRANK_REWARD_BPS as u128)
+        .ok_or(ErrorCode::Overflow)?
+        / 10_000;
+    let reward = u64::try_from(reward).map_err(|_| ErrorCode::Overflow)?;
+    Ok((amount - reward, reward))
+}
+
+/// Authorize the owner, or a delegate holding the permission, to act on the owner's behalf.
+/// Spend by a delegate is charged, in base units, against its cap for each mint.
+pub fn authorize_delegate(
+    owner: &Pubkey,
+    authority: &Pubkey,
+    delegate: Option<&mut Account<Delegate>>,
+    permission: u8,
+    spend: &[(Pubkey, u64)],
+) -> Result<()> {
+    if owner == authority {
+        return Ok(());
+    }
+
+    let delegate = delegate.ok_or(ErrorCode::Unauthorized)?;
+    require_keys_eq!(delegate.owner, *owner, ErrorCode::Unauthorized);
+    require_keys_eq!(delegate.delegate, *authority, ErrorCode::Unauthorized);
+    require!(
+        delegate.permissions & permission == permission,
+        ErrorCode::DelegatePermissionDenied
+    );
+    require_gte!(
+        delegate.expiry_slot,
+        Clock::get()?.slot,
+        ErrorCode::DelegateExpired
+    );
+
+    charge_delegate(delegate, spend)
+}
+
+/// Charge spend by a delegate, in base units, against its cap for each mint
+pub fn charge_delegate(delegate: &mut Delegate, spend: &[(Pubkey, u64)]) -> Result<()> {
+    for (mint, amount) in spend {
+        let spend_cap = delegate
+            .spend_caps
+            .iter_mut()
+            .find(|spend_cap| spend_cap.mint == *mint)
+            .ok_or(ErrorCode::DelegateSpendCapExceeded)?;
+        let spent = spend_cap
+            .spent
+            .checked_add(*amount)
+            .ok_or(ErrorCode::Overflow)?;
+        require_gte!(spend_cap.cap, spent, ErrorCode::DelegateSpendCapExceeded);
+        spend_cap.spent = spent;
+    }
+    Ok(())
+}
+
+/// Return the deposit of a cancelled order to the caps of the delegate that placed it
+pub fn refund_delegate(delegate: &mut Delegate, spend: &[(Pubkey, u64)]) {
+    for (mint, amount) in spend {
+        if let Some(spend_cap) = delegate
+            .spend_caps
+            .iter_mut()
+            .find(|spend_cap| spend_cap.mint == *mint)
+        {
+            spend_cap.spent = spend_cap.spent.saturating_sub(*amount);
+        }
+    }
+}
+
+/// Transfer authority over an owner's tokens: the owner when it signs, otherwise the lend
+/// auction, which the owner approves on its token account so delegates stay within their caps
+pub fn spend_authority<'info>(
+    owner: &Pubkey,
+    authority: &AccountInfo<'info>,
+    lend_auction: &AccountInfo<'info>,
+) -> AccountInfo<'info> {
+    if authority.key == owner {
+        authority.clone()
+    } else {
+        lend_auction.clone()
+    }
+}
+
+/// Match a bid against sorted asks atomically, with a 5% rate difference limit.
+/// Asks owned by the bidder are handled according to the self-trade prevention mode.
+/// Matched asks are returned whole; the caller re-rests the remainder of a partial fill.
+pub fn match_bid(
+    bid: &Bid,
+    asks: &mut Vec<Ask>,
+    self_trade_prevention: SelfTradePrevention,
+) -> Result<MatchOutcome<Ask>> {
+    let mut outcome = MatchOutcome {
+        matches: Vec::new(),
+        cancelled: Vec::new(),
+        taker_cancelled: false,
+    };
     if asks.is_empty() {
-        return Ok(Vec::new());
+        return Ok(outcome);
+    }
+
+    // Cancel the taker before touching the book if it would reach its own ask
+    if self_trade_prevention == SelfTradePrevention::CancelTaker {
+        let mut remaining_amount = bid.amount;
+        for ask in asks.iter().filter(|ask| orders_cross(bid, ask)) {
+            if remaining_amount == 0 {
+                break;
+            }
+            if ask.borrower == bid.lender {
+                return Ok(MatchOutcome::taker_cancelled());
+            }
+            remaining_amount = remaining_amount.saturating_sub(ask.amount);
+        }
     }
 
-    let mut matches = Vec::new();
     let mut remaining_amount = bid.amount;
 
     let mut i = 0;
     while i < asks.len() && remaining_amount > 0 {
         let ask = &asks[i];
-        if ask.max_rate >= bid.min_rate && ask.token_mint == bid.token_mint {
-            let rate_diff = if ask.max_rate > bid.min_rate {
-                ask.max_rate - bid.min_rate
-            } else {
-                bid.min_rate - ask.max_rate
-            };
-
-            if rate_diff <= 5 {
-                let match_amount = cmp::min(remaining_amount, ask.amount);
-                let rate = cmp::min((bid.min_rate + ask.max_rate) / 2, ask.max_rate);
-
-                let matched_ask = asks.remove(i);
-                matches.push((
-                    Ask {
-                        amount: match_amount,
-                        ..matched_ask
-                    },
-                    rate,
-                ));
-                remaining_amount = remaining_amount
-                    .checked_sub(match_amount)
-                    .ok_or(ErrorCode::Overflow)?;
+        if orders_cross(bid, ask) {
+            if ask.borrower == bid.lender {
+                if self_trade_prevention == SelfTradePrevention::CancelResting {
+                    outcome.cancelled.push(asks.remove(i));
+                    continue;
+                }
+                i += 1;
                 continue;
             }
+
+            let match_amount = cmp::min(remaining_amount, ask.amount);
+            let rate = match_rate(bid, ask);
+
+            outcome.matches.push((asks.remove(i), rate));
+            remaining_amount = remaining_amount
+                .checked_sub(match_amount)
+                .ok_or(ErrorCode::Overflow)?;
+            continue;
         }
         i += 1;
     }
 
-    Ok(matches)
+    Ok(outcome)
 }
 
-/// Match an ask against sorted bids atomically, with a 5% rate difference limit
-pub fn match_ask(ask: &Ask, bids: &mut Vec<Bid>) -> Result<Vec<(Bid, u8)>> {
+/// Match an ask against sorted bids atomically, with a 5% rate difference limit.
+/// Bids owned by the asker are handled according to the self-trade prevention mode.
+/// Matched bids are returned whole; the caller re-rests the remainder of a partial fill.
+pub fn match_ask(
+    ask: &Ask,
+    bids: &mut Vec<Bid>,
+    self_trade_prevention: SelfTradePrevention,
+) -> Result<MatchOutcome<Bid>> {
+    let mut outcome = MatchOutcome {
+        matches: Vec::new(),
+        cancelled: Vec::new(),
+        taker_cancelled: false,
+    };
     if bids.is_empty() {
-        return Ok(Vec::new());
+        return Ok(outcome);
+    }
+
+    // Cancel the taker before touching the book if it would reach its own bid
+    if self_trade_prevention == SelfTradePrevention::CancelTaker {
+        let mut remaining_amount = ask.amount;
+        for bid in bids.iter().filter(|bid| orders_cross(bid, ask)) {
+            if remaining_amount == 0 {
+                break;
+            }
+            if bid.lender == ask.borrower {
+                return Ok(MatchOutcome::taker_cancelled());
+            }
+            remaining_amount = remaining_amount.saturating_sub(bid.amount);
+        }
     }
 
-    let mut matches = Vec::new();
     let mut remaining_amount = ask.amount;
 
     let mut i = 0;
     while i < bids.len() && remaining_amount > 0 {
         let bid = &bids[i];
-        if bid.min_rate <= ask.max_rate && bid.token_mint == ask.token_mint {
-            let rate_diff = if bid.min_rate > ask.max_rate {
-                bid.min_rate - ask.max_rate
-            } else {
-                ask.max_rate - bid.min_rate
-            };
-
-            if rate_diff <= 5 {
-                let match_amount = cmp::min(remaining_amount, bid.amount);
-                let rate = cmp::min((bid.min_rate + ask.max_rate) / 2, ask.max_rate);
-
-                let matched_bid = bids.remove(i);
-                matches.push((
-                    Bid {
-                        amount: match_amount,
-                        ..matched_bid
-                    },
-                    rate,
-                ));
-                remaining_amount = remaining_amount
-                    .checked_sub(match_amount)
-                    .ok_or(ErrorCode::Overflow)?;
+        if orders_cross(bid, ask) {
+            if bid.lender == ask.borrower {
+                if self_trade_prevention == SelfTradePrevention::CancelResting {
+                    outcome.cancelled.push(bids.remove(i));
+                    continue;
+                }
+                i += 1;
                 continue;
             }
+
+            let match_amount = cmp::min(remaining_amount, bid.amount);
+            let rate = match_rate(bid, ask);
+
+            outcome.matches.push((bids.remove(i), rate));
+            remaining_amount = remaining_amount
+                .checked_sub(match_amount)
+                .ok_or(ErrorCode::Overflow)?;
+            continue;
         }
         i += 1;
     }
 
-    Ok(matches)
+    Ok(outcome)
+}
+
+/// Check an order's amount against market size limits
+pub fn validate_order_size(market: &Market, amount: u64) -> Result<()> {
+    require_gte!(amount, market.min_amount, ErrorCode::OrderTooSmall);
+    require_gte!(market.max_amount, amount, ErrorCode::OrderTooLarge);
+    Ok(())
+}
+
+/// Check an order's primary collateral against the collateral mint's size limits
+pub fn validate_collateral_size(config: &CollateralConfig, collateral: u64) -> Result<()> {
+    require_gte!(collateral, config.min_collateral, ErrorCode::OrderTooSmall);
+    require_gte!(config.max_collateral, collateral, ErrorCode::OrderTooLarge);
+    Ok(())
+}
+
+/// Check that a partially filled order leaves either nothing or a remainder above the market minimum
+pub fn is_valid_remainder(market: &Market, remaining_amount: u64) -> bool {
+    remaining_amount == 0 || remaining_amount >= market.min_amount
+}
+
+/// Find the best crossing bid/ask pair for a market within a shard.
+/// Bids are scanned from the lowest min_rate and asks from the highest max_rate,
+/// applying the same 5% rate difference limit and initial collateral ratio as submission,
+/// with each ask's collateral valued by `ask_value`.
+/// Pairs where the lender is also the borrower, that would leave a dust remainder, or whose
+/// partially filled ask would keep collateral rejected by `collateral_fits`, are skipped.
+pub fn find_crossing_pair(
+    bids: &[Bid],
+    asks: &[Ask],
+    market: &Market,
+    ask_value: impl Fn(&Ask) -> Result<u64>,
+    collateral_fits: impl Fn(&Ask, u64) -> bool,
+) -> Option<(usize, usize, u8)> {
+    for (bid_idx, bid) in bids.iter().enumerate() {
+        if bid.token_mint != market.token_mint {
+            continue;
+        }
+
+        for (ask_idx, ask) in asks.iter().enumerate() {
+            if !orders_cross(bid, ask)
+                || ask.borrower == bid.lender
+                || !is_valid_remainder(market, bid.amount.abs_diff(ask.amount))
+            {
+                continue;
+            }
+
+            match (required_collateral(market, ask.amount), ask_value(ask)) {
+                (Ok(required), Ok(value)) if value >= required => {}
+                _ => continue,
+            }
+            if ask.amount > bid.amount {
+                match remaining_collateral(ask, bid.amount) {
+                    Ok(remaining) if collateral_fits(ask, remaining) => {}
+                    _ => continue,
+                }
+            }
+
+            return Some((bid_idx, ask_idx, match_rate(bid, ask)));
+        }
+    }
+
+    None
+}
+
+/// Primary collateral left on an ask after `filled` of its amount is lent
+pub fn remaining_collateral(ask: &Ask, filled: u64) -> Result<u64> {
+    let taken = ask
+        .collateral
+        .checked_mul(filled)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(ask.amount)
+        .ok_or(ErrorCode::Overflow)?;
+    Ok(ask
+        .collateral
+        .checked_sub(taken)
+        .ok_or(ErrorCode::Overflow)?)
+}
+
+/// Collateral needed to cover an owed amount at the market's initial collateral ratio
+pub fn required_collateral(market: &Market, amount: u64) -> Result<u64> {
+    let required = (amount as u128)
+        .checked_mul(market.initial_collateral_ratio as u128)
+        .ok_or(ErrorCode::Overflow)?
+        / 100;
+    Ok(u64::try_from(required).map_err(|_| ErrorCode::Overflow)?)
+}
+
+/// Check the basket posted alongside an order's primary collateral: bounded, non-empty
+/// components of distinct supported mints other than the primary collateral mint
+pub fn validate_basket(
+    basket: &[CollateralComponent],
+    collateral_mint: &Pubkey,
+    supported_tokens: &[Pubkey],
+) -> Result<()> {
+    require!(
+        basket.len() <= MAX_BASKET_COMPONENTS,
+        ErrorCode::InvalidBasket
+    );
+    for (i, component) in basket.iter().enumerate() {
+        require!(component.amount > 0, ErrorCode::InvalidBasket);
+        require!(
+            supported_tokens.contains(&component.mint),
+            ErrorCode::UnsupportedCollateral
+        );
+        require!(
+            component.mint != *collateral_mint
+                && !basket[..i].iter().any(|other| other.mint == component.mint),
+            ErrorCode::InvalidBasket
+        );
+    }
+    Ok(())
+}
+
+/// Find and load a collateral mint's haircut configuration among the given accounts by its PDA
+pub fn load_collateral_config<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    mint: &Pubkey,
+) -> Result<Account<'info, CollateralConfig>> {
+    let (address, _) =
+        Pubkey::find_program_address(&[b"collateral_config", mint.as_ref()], &crate::ID);
+    let account = accounts
+        .iter()
+        .find(|account| *account.key == address)
+        .ok_or(ErrorCode::InvalidCollateralConfig)?;
+    Account::try_from(account)
+}
+
+/// Value of primary collateral plus basket components, in base units of the primary collateral
+/// mint. Each component is discounted by its mint's haircut and scaled by the difference in
+/// decimals. With a basket, collateral configs of the primary and basket mints are looked up
+/// in `accounts`.
+pub fn collateral_value<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    collateral_mint: &Pubkey,
+    collateral: u64,
+    basket: &[CollateralComponent],
+) -> Result<u64> {
+    if basket.is_empty() {
+        return Ok(collateral);
+    }
+
+    let decimals = load_collateral_config(accounts, collateral_mint)?.decimals;
+    let mut value = collateral as u128;
+    for component in basket {
+        let config = load_collateral_config(accounts, &component.mint)?;
+        let discounted =
+            (component.amount as u128) * (10_000 - config.haircut_bps as u128) / 10_000;
+        let scaled = if decimals >= config.decimals {
+            discounted.checked_mul(10u128.pow((decimals - config.decimals) as u32))
+        } else {
+            discounted.checked_div(10u128.pow((config.decimals - decimals) as u32))
+        };
+        value = value
+            .checked_add(scaled.ok_or(ErrorCode::Overflow)?)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+    Ok(u64::try_from(value).map_err(|_| ErrorCode::Overflow)?)
+}
+
+/// Share of each basket component for `numerator / denominator` of an order, rounded down
+pub fn split_basket(
+    basket: &[CollateralComponent],
+    numerator: u64,
+    denominator: u64,
+) -> Result<Vec<CollateralComponent>> {
+    basket
+        .iter()
+        .map(|component| {
+            let amount = (component.amount as u128)
+                .checked_mul(numerator as u128)
+                .ok_or(ErrorCode::Overflow)?
+                .checked_div(denominator as u128)
+                .ok_or(ErrorCode::Overflow)?;
+            Ok(CollateralComponent {
+                mint: component.mint,
+                amount: amount as u64,
+            })
+        })
+        .collect()
+}
+
+/// Move basket components from one owner's associated token accounts to another's.
+/// Both accounts of every component are looked up in `accounts`.
+pub fn transfer_basket<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    basket: &[CollateralComponent],
+    from_owner: &Pubkey,
+    to_owner: &Pubkey,
+    authority: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    for component in basket.iter().filter(|component| component.amount > 0) {
+        let find = |owner: &Pubkey| {
+            let address = get_associated_token_address(owner, &component.mint);
+            accounts
+                .iter()
+                .find(|account| *account.key == address)
+                .ok_or(ErrorCode::InvalidBasketAccount)
+        };
+        transfer(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                Transfer {
+                    from: find(from_owner)?.clone(),
+                    to: find(to_owner)?.clone(),
+                    authority: authority.clone(),
+                },
+                signer_seeds,
+            ),
+            component.amount,
+        )?;
+    }
+    Ok(())
+}
+
+/// Settle an open loan in full with `total_due` paid from `payer_token_account` by `payer`.
+/// The payment is held in the vault until the lender side claims it; the collateral and the
+/// basket, between the associated token accounts in `basket_accounts`, return to the borrower.
+#[allow(clippy::too_many_arguments)]
+pub fn settle_repayment<'info>(
+    loan: &mut Loan,
+    total_due: u64,
+    payer: &AccountInfo<'info>,
+    payer_token_account: &AccountInfo<'info>,
+    vault_token_account: &AccountInfo<'info>,
+    vault_collateral_account: &AccountInfo<'info>,
+    borrower_collateral_account: &AccountInfo<'info>,
+    lender_positions: &mut UserPositions,
+    borrower_positions: &mut UserPositions,
+    basket_accounts: &'info [AccountInfo<'info>],
+    lend_auction: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    loan.claimable = loan
+        .claimable
+        .checked_add(total_due)
+        .ok_or(ErrorCode::Overflow)?;
+    loan.status = LoanStatus::Repaid;
+
+    transfer(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            Transfer {
+                from: payer_token_account.clone(),
+                to: vault_token_account.clone(),
+                authority: payer.clone(),
+            },
+            signer_seeds,
+        ),
+        total_due,
+    )?;
+    transfer(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            Transfer {
+                from: vault_collateral_account.clone(),
+                to: borrower_collateral_account.clone(),
+                authority: lend_auction.clone(),
+            },
+            signer_seeds,
+        ),
+        loan.collateral,
+    )?;
+    transfer_basket(
+        basket_accounts,
+        &loan.basket,
+        lend_auction.key,
+        &loan.borrower,
+        lend_auction,
+        token_program,
+        signer_seeds,
+    )?;
+
+    close_loan_position(lender_positions, loan)?;
+    close_loan_position(borrower_positions, loan)
+}
+
+/// Create and write the PDA account of a new loan, seeded by its global loan id
+pub fn create_loan_account<'info>(
+    loan_account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    loan: &Loan,
+) -> Result<()> {
+    let loan_id_bytes = loan.loan_id.to_le_bytes();
+    let (loan_address, bump) = Pubkey::find_program_address(&[b"loan", &loan_id_bytes], &crate::ID);
+    require_keys_eq!(
+        *loan_account.key,
+        loan_address,
+        ErrorCode::InvalidLoanAccount
+    );
+
+    // Like Anchor's `init`, tolerate lamports sent to the address ahead of creation
+    let signer_seeds: &[&[&[u8]]] = &[&[b"loan", &loan_id_bytes, &[bump]]];
+    let rent = Rent::get()?.minimum_balance(Loan::SPACE);
+    let current_lamports = loan_account.lamports();
+    if current_lamports == 0 {
+        create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                CreateAccount {
+                    from: payer.clone(),
+                    to: loan_account.clone(),
+                },
+                signer_seeds,
+            ),
+            rent,
+            Loan::SPACE as u64,
+            &crate::ID,
+        )?;
+    } else {
+        let shortfall = rent.saturating_sub(current_lamports);
+        if shortfall > 0 {
+            system_transfer(
+                CpiContext::new(
+                    system_program.clone(),
+                    SystemTransfer {
+                        from: payer.clone(),
+                        to: loan_account.clone(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        allocate(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                Allocate {
+                    account_to_allocate: loan_account.clone(),
+                },
+                signer_seeds,
+            ),
+            Loan::SPACE as u64,
+        )?;
+        assign(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                Assign {
+                    account_to_assign: loan_account.clone(),
+                },
+                signer_seeds,
+            ),
+            &crate::ID,
+        )?;
+    }
+
+    let mut data = loan_account.try_borrow_mut_data()?;
+    loan.try_serialize(&mut &mut data[..])?;
+    Ok(())
+}
+
+/// Find and load a wallet's position index among the given accounts by its PDA
+pub fn load_user_positions<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    owner: &Pubkey,
+) -> Result<Account<'info, UserPositions>> {
+    let (address, _) =
+        Pubkey::find_program_address(&[b"user_positions", owner.as_ref()], &crate::ID);
+    let account = accounts
+        .iter()
+        .find(|account| *account.key == address)
+        .ok_or(ErrorCode::InvalidPositionsAccount)?;
+    Account::try_from(account)
+}
+
+/// Token account that receives a borrower's loan proceeds: the recorded payout account,
+/// otherwise the borrower's associated token account for the loan token
+pub fn proceeds_destination(
+    borrower: &Pubkey,
+    token_mint: &Pubkey,
+    payout: Option<Pubkey>,
+) -> Pubkey {
+    payout.unwrap_or_else(|| get_associated_token_address(borrower, token_mint))
+}
+
+/// Find a borrower's proceeds account among the given accounts and check it holds the loan token
+pub fn load_proceeds_account<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    destination: &Pubkey,
+    token_mint: &Pubkey,
+) -> Result<&'info AccountInfo<'info>> {
+    let account = accounts
+        .iter()
+        .find(|account| account.key == destination)
+        .ok_or(ErrorCode::MissingBorrowerAccount)?;
+    let token_account = Account::<TokenAccount>::try_from(account)?;
+    require_keys_eq!(
+        token_account.mint,
+        *token_mint,
+        ErrorCode::InvalidTokenAccount
+    );
+    Ok(account)
+}
+
+/// Track or untrack the shards in which the wallet still has resting orders
+pub fn sync_order_shards(positions: &mut UserPositions, shard_pool: &ShardPool) -> Result<()> {
+    let owner = positions.owner;
+    let has_bid = shard_pool.bids.iter().any(|bid| bid.lender == owner);
+    let has_ask = shard_pool.asks.iter().any(|ask| ask.borrower == owner);
+    sync_shard(&mut positions.bid_shards, shard_pool.shard_id, has_bid);
+    sync_shard(&mut positions.ask_shards, shard_pool.shard_id, has_ask);
+    Ok(())
+}
+
+fn sync_shard(shards: &mut Vec<u64>, shard_id: u64, open: bool) {
+    let tracked = shards.contains(&shard_id);
+    if open && !tracked {
+        shards.push(shard_id);
+    } else if !open && tracked {
+        shards.retain(|id| *id != shard_id);
+    }
+}
+
+/// Totals entry for a mint in a wallet's position index, added on first use
+fn position_balance<'a>(
+    positions: &'a mut UserPositions,
+    mint: &Pubkey,
+) -> &'a mut PositionBalance {
+    let index = match positions
+        .balances
+        .iter()
+        .position(|balance| balance.mint == *mint)
+    {
+        Some(index) => index,
+        None => {
+            positions.balances.push(PositionBalance {
+                mint: *mint,
+                supplied: 0,
+                borrowed: 0,
+                collateral_posted: 0,
+            });
+            positions.balances.len() - 1
+        }
+    };
+    &mut positions.balances[index]
+}
+
+/// Add collateral posted in a mint to a borrower's totals
+pub fn post_collateral(positions: &mut UserPositions, mint: &Pubkey, amount: u64) -> Result<()> {
+    let balance = position_balance(positions, mint);
+    balance.collateral_posted = balance
+        .collateral_posted
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+    Ok(())
+}
+
+/// Remove collateral returned, withdrawn or sold in a mint from a borrower's totals
+pub fn release_collateral(positions: &mut UserPositions, mint: &Pubkey, amount: u64) -> Result<()> {
+    let balance = position_balance(positions, mint);
+    balance.collateral_posted = balance
+        .collateral_posted
+        .checked_sub(amount)
+        .ok_or(ErrorCode::Overflow)?;
+    Ok(())
+}
+
+/// Add a newly issued loan to the lender's or borrower's position index
+pub fn open_loan_position(positions: &mut UserPositions, loan: &Loan) -> Result<()> {
+    positions.loans.push(loan.loan_id);
+
+    if positions.owner == loan.lender {
+        let balance = position_balance(positions, &loan.token_mint);
+        balance.supplied = balance
+            .supplied
+            .checked_add(loan.amount)
+            .ok_or(ErrorCode::Overflow)?;
+    } else {
+        require_keys_eq!(
+            positions.owner,
+            loan.borrower,
+            ErrorCode::InvalidPositionsAccount
+        );
+        let balance = position_balance(positions, &loan.token_mint);
+        balance.borrowed = balance
+            .borrowed
+            .checked_add(loan.amount)
+            .ok_or(ErrorCode::Overflow)?;
+        post_collateral(positions, &loan.collateral_mint, loan.collateral)?;
+        for component in &loan.basket {
+            post_collateral(positions, &component.mint, component.amount)?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove a closed loan from the lender's or borrower's position index
+pub fn close_loan_position(positions: &mut UserPositions, loan: &Loan) -> Result<()> {
+    positions.loans.retain(|loan_id| *loan_id != loan.loan_id);
+    reduce_loan_position(positions, loan, loan.amount, loan.collateral)?;
+    if positions.owner != loan.lender {
+        for component in &loan.basket {
+            release_collateral(positions, &component.mint, component.amount)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reduce a participant's totals by repaid principal and released primary collateral
+pub fn reduce_loan_position(
+    positions: &mut UserPositions,
+    loan: &Loan,
+    principal: u64,
+    collateral: u64,
+) -> Result<()> {
+    if positions.owner == loan.lender {
+        let balance = position_balance(positions, &loan.token_mint);
+        balance.supplied = balance
+            .supplied
+            .checked_sub(principal)
+            .ok_or(ErrorCode::Overflow)?;
+    } else {
+        require_keys_eq!(
+            positions.owner,
+            loan.borrower,
+            ErrorCode::InvalidPositionsAccount
+        );
+        let balance = position_balance(positions, &loan.token_mint);
+        balance.borrowed = balance
+            .borrowed
+            .checked_sub(principal)
+            .ok_or(ErrorCode::Overflow)?;
+        release_collateral(positions, &loan.collateral_mint, collateral)?;
+    }
+    Ok(())
+}
+
+/// Grow a position index account to fit its entries, the payer funding the extra rent
+pub fn fit_user_positions<'info>(
+    positions: &Account<'info, UserPositions>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let account = positions.to_account_info();
+    let space = 8 + positions.try_to_vec()?.len();
+    if space <= account.data_len() {
+        return Ok(());
+    }
+
+    let shortfall = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(account.lamports());
+    if shortfall > 0 {
+        system_transfer(
+            CpiContext::new(
+                system_program.clone(),
+                SystemTransfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            shortfall,
+        )?;
+    }
+    account.realloc(space, false)?;
+    Ok(())
+}
+
+/// Current owner of a loan's lender side: the holder of its position token once tokenized,
+/// otherwise the originating lender
+pub fn lender_side_owner(
+    loan: &Loan,
+    position_token_account: Option<&Account<TokenAccount>>,
+) -> Result<Pubkey> {
+    let Some(position_mint) = loan.position_mint else {
+        return Ok(loan.lender);
+    };
+    let position_token_account = position_token_account.ok_or(ErrorCode::InvalidPositionToken)?;
+    require_keys_eq!(
+        position_token_account.mint,
+        position_mint,
+        ErrorCode::InvalidPositionToken
+    );
+    require_eq!(
+        position_token_account.amount,
+        1,
+        ErrorCode::InvalidPositionToken
+    );
+    Ok(position_token_account.owner)
+}
+
+/// Compute principal plus interest owed on a loan at the given unix timestamp, rounded up.
+/// Interest accrues on outstanding principal since the last accrual checkpoint.
+pub fn compute_repayment(loan: &Loan, now: i64) -> Result<u64> {
+    let elapsed_seconds = now.saturating_sub(loan.last_accrual_ts).max(0) as u64;
+    let interest = apply_wad_up(
+        loan.amount,
+        accrued_interest_wad(loan.rate, elapsed_seconds)?,
+    )?;
+    Ok(loan
+        .amount
+        .checked_add(loan.accrued_interest)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_add(interest)
+        .ok_or(ErrorCode::Overflow)?)
+}
+
+/// Check an early-repayment policy against the order duration
+pub fn validate_early_repayment(
+    early_repayment: EarlyRepayment,
+    duration_slots: u64,
+) -> Result<()> {
+    if let EarlyRepayment::MinimumSlots(slots) = early_repayment {
+        require!(
+            slots > 0 && slots <= duration_slots,
+            ErrorCode::InvalidEarlyRepayment
+        );
+    }
+    Ok(())
+}
+
+/// Timestamp until which interest is owed even when the loan is repaid earlier
+pub fn minimum_interest_until(loan: &Loan) -> Result<i64> {
+    match loan.early_repayment {
+        EarlyRepayment::FullTerm => Ok(loan.maturity_ts),
+        EarlyRepayment::MinimumSlots(slots) => compute_maturity(loan.start_ts, slots),
+        EarlyRepayment::ProRata => Ok(loan.start_ts),
+    }
+}
+
+/// Timestamp a payment made at `now` accrues interest to, never before the minimum-interest horizon
+pub fn repayment_accrual_ts(loan: &Loan, now: i64) -> Result<i64> {
+    Ok(cmp::max(now, minimum_interest_until(loan)?))
+}
+
+/// Compute the principal and interest owed to settle a loan at `now`, excluding late penalty
+pub fn compute_repayment_due(loan: &Loan, now: i64) -> Result<u64> {
+    compute_repayment(loan, repayment_accrual_ts(loan, now)?)
+}
+
+/// Compute the late penalty owed: the checkpointed penalty plus penalty accrued at the market
+/// penalty APR on the balance owed at maturity, or at the last accrual checkpoint when that is
+/// later. The balance excludes earlier penalty, so penalty never compounds.
+pub fn compute_penalty(loan: &Loan, penalty_rate: u8, now: i64) -> Result<u64> {
+    let overdue_since = cmp::max(loan.maturity_ts, loan.last_accrual_ts);
+    if now <= overdue_since {
+        return Ok(loan.accrued_penalty);
+    }
+    let overdue_balance = compute_repayment(loan, overdue_since)?;
+    let overdue_seconds = (now - overdue_since) as u64;
+    let penalty = apply_wad_up(
+        overdue_balance,
+        accrued_interest_wad(penalty_rate, overdue_seconds)?,
+    )?;
+    Ok(loan
+        .accrued_penalty
+        .checked_add(penalty)
+        .ok_or(ErrorCode::Overflow)?)
+}
+
+/// Compute the repayment and late penalty owed to settle a loan in full at `now`
+pub fn compute_amount_due(loan: &Loan, penalty_rate: u8, now: i64) -> Result<(u64, u64)> {
+    // Early repayment still owes interest up to the loan's minimum-interest horizon
+    let repayment = compute_repayment_due(loan, now)?;
+    let penalty = compute_penalty(loan, penalty_rate, now)?;
+    Ok((repayment, penalty))
+}
+
+/// Compute loan health as collateral over repayment, in percent
+pub fn compute_health_factor(collateral: u64, repayment: u64) -> Result<u64> {
+    let health_factor = (collateral as u128)
+        .checked_mul(100)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(repayment as u128)
+        .ok_or(ErrorCode::Overflow)?;
+    Ok(u64::try_from(health_factor).unwrap_or(u64::MAX))
 }
+
+/// Aggregate resting orders into price levels, keeping book order
+pub fn aggregate_depth<I>(orders: I, levels: usize) -> Result<Vec<DepthLevel>>
+where
+    I: Iterator<Item = (u8, u64)>,
+{
+    let mut depth: Vec<DepthLevel> = Vec::new();
+    for (rate, amount) in orders {
+        match depth.last_mut() {
+            Some(level) if level.rate == rate => {
+                level.amount = level
+                    .amount
+                    .checked_add(amount)
+                    .ok_or(ErrorCode::Overflow)?;
+                level.orders += 1;
+            }
+            _ => {
+                if depth.len() == levels {
+                    break;
+                }
+                depth.push(DepthLevel {
+                    rate,
+                    amount,
+                    orders: 1,
+                });
+            }
+        }
+    }
+    Ok(depth)
+}
+
 /// Insert bid into sorted Vec (ascending by min_rate)
 pub fn insert_sorted_bid(shard_pool: &mut ShardPool, bid: Bid) {
     let idx = shard_pool
@@ -121,7 +987,51 @@ pub fn insert_sorted_ask(shard_pool: &mut ShardPool, ask: Ask) {
     shard_pool.asks.insert(idx, ask);
 }
 
+/// Verify an Ed25519 sigverify precompile instruction covers `message` signed by `signer`.
+/// Signature, public key and message must all be embedded in the precompile instruction itself.
+pub fn verify_ed25519_instruction(ix: &Instruction, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    require_keys_eq!(
+        ix.program_id,
+        ed25519_program::ID,
+        ErrorCode::InvalidSignature
+    );
+    require!(ix.accounts.is_empty(), ErrorCode::InvalidSignature);
+
+    // Header: signature count (1 byte), padding (1 byte), then one 14-byte offsets record
+    let data = &ix.data;
+    require!(
+        data.len() >= 16 && data[0] == 1,
+        ErrorCode::InvalidSignature
+    );
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let signature_instruction_index = read_u16(4);
+    let public_key_offset = read_u16(6) as usize;
+    let public_key_instruction_index = read_u16(8);
+    let message_data_offset = read_u16(10) as usize;
+    let message_data_size = read_u16(12) as usize;
+    let message_instruction_index = read_u16(14);
+    require!(
+        signature_instruction_index == u16::MAX
+            && public_key_instruction_index == u16::MAX
+            && message_instruction_index == u16::MAX,
+        ErrorCode::InvalidSignature
+    );
+
+    let public_key = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(ErrorCode::InvalidSignature)?;
+    let signed_message = data
+        .get(message_data_offset..message_data_offset + message_data_size)
+        .ok_or(ErrorCode::InvalidSignature)?;
+    require!(
+        public_key == signer.as_ref() && signed_message == message,
+        ErrorCode::InvalidSignature
+    );
+    Ok(())
+}
+
 /// Create Raydium swap instruction (simplified for SwapBaseIn)
+#[allow(clippy::too_many_arguments)]
 pub fn create_raydium_swap_instruction(
     program_id: &AccountInfo,
     amm: &AccountInfo,
@@ -143,7 +1053,7 @@ pub fn create_raydium_swap_instruction(
     amount_in: u64,
     minimum_amount_out: u64,
 ) -> Result<anchor_lang::solana_program::instruction::Instruction> {
-    let data = vec![
+    let data = [
         vec![9u8], // Raydium SwapBaseIn instruction ID (based on Raydium's layout)
         amount_in.to_le_bytes().to_vec(),
         minimum_amount_out.to_le_bytes().to_vec(),
@@ -174,3 +1084,265 @@ pub fn create_raydium_swap_instruction(
         data,
     })
 }
+
+/// Create Raydium SwapBaseOut instruction: receive exactly `amount_out` for at most `max_amount_in`
+#[allow(clippy::too_many_arguments)]
+pub fn create_raydium_swap_base_out_instruction(
+    program_id: &AccountInfo,
+    amm: &AccountInfo,
+    amm_authority: &AccountInfo,
+    amm_open_orders: &AccountInfo,
+    amm_coin_vault: &AccountInfo,
+    amm_pc_vault: &AccountInfo,
+    market_program: &AccountInfo,
+    market: &AccountInfo,
+    market_bids: &AccountInfo,
+    market_asks: &AccountInfo,
+    market_event_queue: &AccountInfo,
+    market_coin_vault: &AccountInfo,
+    market_pc_vault: &AccountInfo,
+    market_vault_signer: &AccountInfo,
+    user_token_source: &AccountInfo,
+    user_token_destination: &AccountInfo,
+    user_source_owner: &AccountInfo,
+    max_amount_in: u64,
+    amount_out: u64,
+) -> Result<anchor_lang::solana_program::instruction::Instruction> {
+    // Same accounts and data layout as SwapBaseIn, with the amounts' roles swapped
+    let mut instruction = create_raydium_swap_instruction(
+        program_id,
+        amm,
+        amm_authority,
+        amm_open_orders,
+        amm_coin_vault,
+        amm_pc_vault,
+        market_program,
+        market,
+        market_bids,
+        market_asks,
+        market_event_queue,
+        market_coin_vault,
+        market_pc_vault,
+        market_vault_signer,
+        user_token_source,
+        user_token_destination,
+        user_source_owner,
+        max_amount_in,
+        amount_out,
+    )?;
+    instruction.data[0] = 11; // Raydium SwapBaseOut instruction ID
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bid(lender: Pubkey, amount: u64, min_rate: u8) -> Bid {
+        Bid {
+            lender,
+            amount,
+            min_rate,
+            slot: 0,
+            token_mint: Pubkey::default(),
+            duration_slots: 100,
+            early_repayment: EarlyRepayment::ProRata,
+            delegate: None,
+        }
+    }
+
+    fn loan(amount: u64, rate: u8, maturity_ts: i64) -> Loan {
+        Loan {
+            loan_id: 0,
+            payer: Pubkey::default(),
+            lender: Pubkey::new_unique(),
+            borrower: Pubkey::new_unique(),
+            amount,
+            rate,
+            collateral: amount * 2,
+            status: LoanStatus::Active,
+            shard_id: 0,
+            token_mint: Pubkey::default(),
+            collateral_mint: Pubkey::default(),
+            start_ts: 0,
+            maturity_ts,
+            accrued_interest: 0,
+            accrued_penalty: 0,
+            last_accrual_ts: 0,
+            early_repayment: EarlyRepayment::ProRata,
+            position_mint: None,
+            claimable: 0,
+            basket: Vec::new(),
+        }
+    }
+
+    fn ask(borrower: Pubkey, amount: u64, max_rate: u8) -> Ask {
+        Ask {
+            borrower,
+            amount,
+            max_rate,
+            collateral: amount * 2,
+            slot: 0,
+            token_mint: Pubkey::default(),
+            collateral_mint: Pubkey::default(),
+            payout: None,
+            basket: Vec::new(),
+            delegate: None,
+        }
+    }
+
+    #[test]
+    fn match_rate_does_not_overflow_at_high_rates() {
+        let lender = Pubkey::new_unique();
+        let borrower = Pubkey::new_unique();
+        assert_eq!(
+            match_rate(&bid(lender, 1, 250), &ask(borrower, 1, 255)),
+            252
+        );
+        assert_eq!(
+            match_rate(&bid(lender, 1, 255), &ask(borrower, 1, 255)),
+            255
+        );
+        assert_eq!(match_rate(&bid(lender, 1, 10), &ask(borrower, 1, 14)), 12);
+    }
+
+    #[test]
+    fn match_bid_fills_asks_priced_near_the_top_of_the_range() {
+        let mut asks = vec![ask(Pubkey::new_unique(), 100, 255)];
+        let outcome = match_bid(
+            &bid(Pubkey::new_unique(), 100, 252),
+            &mut asks,
+            SelfTradePrevention::CancelTaker,
+        )
+        .unwrap();
+        assert_eq!(outcome.matches.len(), 1);
+        assert_eq!(outcome.matches[0].1, 253);
+        assert!(asks.is_empty());
+    }
+
+    #[test]
+    fn match_ask_fills_bids_priced_near_the_top_of_the_range() {
+        let mut bids = vec![bid(Pubkey::new_unique(), 100, 251)];
+        let outcome = match_ask(
+            &ask(Pubkey::new_unique(), 100, 254),
+            &mut bids,
+            SelfTradePrevention::CancelTaker,
+        )
+        .unwrap();
+        assert_eq!(outcome.matches.len(), 1);
+        assert_eq!(outcome.matches[0].1, 252);
+        assert!(bids.is_empty());
+    }
+
+    #[test]
+    fn crank_reward_is_carved_from_the_matched_amount() {
+        assert_eq!(split_crank_reward(1_000_000).unwrap(), (999_500, 500));
+        assert_eq!(split_crank_reward(1_999).unwrap(), (1_999, 0));
+
+        // Proceeds and reward always add back up to the loan, so no other funds are spent
+        for amount in [0, 1, 10_000, 123_456_789, u64::MAX] {
+            let (proceeds, reward) = split_crank_reward(amount).unwrap();
+            assert_eq!(proceeds + reward, amount);
+            assert!(reward <= amount / 2_000);
+        }
+    }
+
+    #[test]
+    fn find_crossing_pair_skips_asks_left_with_out_of_bounds_collateral() {
+        let market = Market {
+            token_mint: Pubkey::default(),
+            min_amount: 100,
+            max_amount: 10_000,
+            grace_period: 0,
+            penalty_rate: 0,
+            initial_collateral_ratio: 150,
+        };
+        let bids = vec![bid(Pubkey::new_unique(), 100, 10)];
+        let asks = vec![ask(Pubkey::new_unique(), 300, 12)];
+
+        // Filling 100 of the ask leaves 400 of its 600 collateral resting
+        assert_eq!(remaining_collateral(&asks[0], 100).unwrap(), 400);
+        assert_eq!(
+            find_crossing_pair(
+                &bids,
+                &asks,
+                &market,
+                |ask| Ok(ask.collateral),
+                |_, remaining| { remaining >= 500 }
+            ),
+            None
+        );
+        assert_eq!(
+            find_crossing_pair(
+                &bids,
+                &asks,
+                &market,
+                |ask| Ok(ask.collateral),
+                |_, remaining| { remaining >= 400 }
+            ),
+            Some((0, 0, 11))
+        );
+    }
+
+    #[test]
+    fn penalty_accrues_on_interest_but_not_on_earlier_penalty() {
+        let year = crate::math::SECONDS_PER_YEAR as i64;
+        let mut loan = loan(1_000_000, 0, 0);
+        loan.accrued_interest = 100_000;
+        loan.accrued_penalty = 500_000;
+
+        // A year overdue at a 100% penalty APR charges the 1_100_000 owed, not the penalty
+        assert_eq!(compute_penalty(&loan, 100, year).unwrap(), 1_600_000);
+        // Before it is overdue again, only the checkpointed penalty is owed
+        loan.last_accrual_ts = year;
+        assert_eq!(compute_penalty(&loan, 100, year).unwrap(), 500_000);
+    }
+
+    #[test]
+    fn aggregate_depth_merges_equal_rates_and_caps_levels() {
+        let orders = [(10, 100), (10, 50), (12, 30), (15, 20), (15, 5)];
+        let levels = |depth: Vec<DepthLevel>| {
+            depth
+                .into_iter()
+                .map(|level| (level.rate, level.amount, level.orders))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            levels(aggregate_depth(orders.into_iter(), 2).unwrap()),
+            vec![(10, 150, 2), (12, 30, 1)]
+        );
+        assert_eq!(
+            levels(aggregate_depth(orders.into_iter(), 5).unwrap()),
+            vec![(10, 150, 2), (12, 30, 1), (15, 25, 2)]
+        );
+        assert!(aggregate_depth([(10, u64::MAX), (10, 1)].into_iter(), 1).is_err());
+    }
+
+    #[test]
+    fn early_repayment_owes_interest_up_to_the_policy_horizon() {
+        let year = crate::math::SECONDS_PER_YEAR as i64;
+        let mut loan = loan(1_000_000, 10, year);
+        let repaid_at = year / 4;
+
+        // Pro-rata pays only for the quarter elapsed
+        assert_eq!(compute_repayment_due(&loan, repaid_at).unwrap(), 1_025_000);
+
+        // Full-term pays the whole year regardless of when it repays
+        loan.early_repayment = EarlyRepayment::FullTerm;
+        assert_eq!(compute_repayment_due(&loan, repaid_at).unwrap(), 1_100_000);
+
+        // A minimum of half the term in slots pays half a year until then, then pro-rata
+        let half_term_slots = (year as u64 / 2) * 1000 / crate::math::DEFAULT_MS_PER_SLOT;
+        loan.early_repayment = EarlyRepayment::MinimumSlots(half_term_slots);
+        assert_eq!(compute_repayment_due(&loan, repaid_at).unwrap(), 1_050_000);
+        assert_eq!(
+            compute_repayment_due(&loan, year * 3 / 4).unwrap(),
+            1_075_000
+        );
+
+        assert!(validate_early_repayment(EarlyRepayment::MinimumSlots(0), 100).is_err());
+        assert!(validate_early_repayment(EarlyRepayment::MinimumSlots(101), 100).is_err());
+        assert!(validate_early_repayment(EarlyRepayment::MinimumSlots(100), 100).is_ok());
+    }
+}
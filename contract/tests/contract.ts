@@ -1,9 +1,30 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { Contract } from "../target/types/contract";
-import { Keypair, LAMPORTS_PER_SOL, PublicKey, Signer } from "@solana/web3.js";
+import {
+  AccountMeta,
+  Ed25519Program,
+  Keypair,
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  Signer,
+  SystemProgram,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+} from "@solana/web3.js";
 import { assert } from "chai";
-import { createAccount, createMint, getAccount, getOrCreateAssociatedTokenAccount, mintTo } from "@solana/spl-token";
+import {
+  approve,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  createAccount,
+  createMint,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+  TOKEN_PROGRAM_ID,
+  transfer,
+} from "@solana/spl-token";
 import { sha256 } from "js-sha256";
 import { readFileSync } from "fs";
 import path from 'path';
@@ -44,20 +65,45 @@ describe("contract", () => {
     secretKey: admin.secretKey
   }
 
-  // Generate keypairs for mock supported tokens
+  // Mock supported tokens, minted by the admin
   let tokenMint: PublicKey;
   let collateralMint: PublicKey;
+  // Collateral posted in baskets alongside the primary collateral
+  let basketMint: PublicKey;
+
+  // Lend auction vaults for each supported mint
+  let vaultTokenAccount: PublicKey;
+  let vaultCollateralAccount: PublicKey;
+  let vaultBasketAccount: PublicKey;
+
+  // Market limits, in token base units, and collateral limits, in collateral base units
+  const minAmount = 100000;
+  const maxAmount = 10000000;
+  const minCollateral = 100000;
+  const maxCollateral = 50000000;
+  const initialCollateralRatio = 150;
+  const basketHaircutBps = 2000;
+
+  // Signed orders are prefixed with this domain and the program id
+  const signedOrderDomain = Buffer.from("lend_auction:signed_order");
+
+  const [lendAuctionPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("lend_auction")],
+    program.programId
+  );
 
   before(async () => {
-    // await provider.connection.confirmTransaction(
-    //   await provider.connection.requestAirdrop(admin.publicKey, 10 * LAMPORTS_PER_SOL),
-    //   "confirmed"
-    // );
-    // tokenMint = await createMint(provider.connection, admin, admin.publicKey, null, 6);
-    // collateralMint = await createMint(provider.connection, admin, admin.publicKey, null, 6);
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(admin.publicKey, 10 * LAMPORTS_PER_SOL),
+      "confirmed"
+    );
+    tokenMint = await createMint(provider.connection, admin, admin.publicKey, null, 6);
+    collateralMint = await createMint(provider.connection, admin, admin.publicKey, null, 6);
+    basketMint = await createMint(provider.connection, admin, admin.publicKey, null, 6);
 
-    collateralMint = new PublicKey("So11111111111111111111111111111111111111112")
-    tokenMint = new PublicKey("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v")
+    vaultTokenAccount = await tokenAccount(tokenMint, lendAuctionPda);
+    vaultCollateralAccount = await tokenAccount(collateralMint, lendAuctionPda);
+    vaultBasketAccount = await tokenAccount(basketMint, lendAuctionPda);
   });
 
   it("Is initialized!", async () => {
@@ -86,7 +132,7 @@ describe("contract", () => {
     
     // Parameters for initialization
     const shardCount = new anchor.BN(1);
-    const supportedTokens = [tokenMint, collateralMint];
+    const supportedTokens = [tokenMint, collateralMint, basketMint];
 
     const info = await program.provider.connection.getAccountInfo(lendAuctionPda);
     if (!info) {
@@ -177,6 +223,221 @@ describe("contract", () => {
     return hashNum.mod(new anchor.BN(shardCount));
   }
 
+  function pda(...seeds: Buffer[]): PublicKey {
+    return PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+  }
+
+  function u64(value: anchor.BN | number): Buffer {
+    return new anchor.BN(value).toArrayLike(Buffer, "le", 8);
+  }
+
+  function writable(pubkey: PublicKey): AccountMeta {
+    return { pubkey, isSigner: false, isWritable: true };
+  }
+
+  function readonly(pubkey: PublicKey): AccountMeta {
+    return { pubkey, isSigner: false, isWritable: false };
+  }
+
+  const marketPda = (mint: PublicKey) => pda(Buffer.from("market"), mint.toBuffer());
+  const collateralConfigPda = (mint: PublicKey) => pda(Buffer.from("collateral_config"), mint.toBuffer());
+  const positionsPda = (owner: PublicKey) => pda(Buffer.from("user_positions"), owner.toBuffer());
+  const loanPda = (loanId: anchor.BN) => pda(Buffer.from("loan"), u64(loanId));
+
+  async function fundedWallet(): Promise<Keypair> {
+    const wallet = Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(wallet.publicKey, 2 * LAMPORTS_PER_SOL),
+      "confirmed"
+    );
+    return wallet;
+  }
+
+  // Associated token account of `owner`, created if needed and topped up with `amount`
+  async function tokenAccount(mint: PublicKey, owner: PublicKey, amount = 0): Promise<PublicKey> {
+    const account = await getOrCreateAssociatedTokenAccount(provider.connection, admin, mint, owner, true);
+    if (amount > 0) {
+      await mintTo(provider.connection, admin, mint, account.address, admin, amount);
+    }
+    return account.address;
+  }
+
+  async function balance(account: PublicKey): Promise<number> {
+    return Number((await getAccount(provider.connection, account)).amount);
+  }
+
+  function shardAccounts(rate: number) {
+    const shardId = computeShardId(tokenMint, rate, 1);
+    return {
+      shardId,
+      shardPool: pda(Buffer.from("shard_pool"), u64(shardId)),
+      loanPool: pda(Buffer.from("loan_pool"), u64(shardId)),
+    };
+  }
+
+  async function nextLoanId(): Promise<anchor.BN> {
+    return (await program.account.lendAuction.fetch(lendAuctionPda)).totalLoans;
+  }
+
+  function askAccounts(asker: PublicKey, authority: PublicKey, rate: number, askerCollateralAccount: PublicKey, borrowerTokenAccount: PublicKey) {
+    const { shardPool, loanPool } = shardAccounts(rate);
+    return {
+      lendAuction: lendAuctionPda,
+      market: marketPda(tokenMint),
+      collateralConfig: collateralConfigPda(collateralMint),
+      shardPool,
+      loanPool,
+      authority,
+      asker,
+      delegate: null,
+      askerPositions: positionsPda(asker),
+      askerCollateralAccount,
+      borrowerTokenAccount,
+      payoutTokenAccount: null,
+      vaultTokenAccount,
+      vaultCollateralAccount,
+      tokenMint,
+      collateralMint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+  }
+
+  function bidAccounts(bidder: PublicKey, authority: PublicKey, rate: number, bidderTokenAccount: PublicKey) {
+    const { shardPool, loanPool } = shardAccounts(rate);
+    return {
+      lendAuction: lendAuctionPda,
+      market: marketPda(tokenMint),
+      shardPool,
+      loanPool,
+      authority,
+      bidder,
+      delegate: null,
+      bidderPositions: positionsPda(bidder),
+      bidderTokenAccount,
+      vaultTokenAccount,
+      bidderCollateralAccount: null,
+      vaultCollateralAccount: null,
+      tokenMint,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    };
+  }
+
+  // Rest an ask of 500000 against 750000 collateral and fill it with a matching bid
+  async function issueLoan(rate: number, durationSlots = 1000, earlyRepayment: object = { proRata: {} }) {
+    const borrower = await fundedWallet();
+    const borrowerCollateralAccount = await tokenAccount(collateralMint, borrower.publicKey, 750000);
+    const borrowerTokenAccount = await tokenAccount(tokenMint, borrower.publicKey);
+    await program.methods
+      .submitAsk(new anchor.BN(500000), rate, new anchor.BN(750000), [], { cancelTaker: {} })
+      .accountsPartial(askAccounts(borrower.publicKey, borrower.publicKey, rate, borrowerCollateralAccount, borrowerTokenAccount))
+      .signers([borrower])
+      .rpc();
+
+    const lender = await fundedWallet();
+    const lenderTokenAccount = await tokenAccount(tokenMint, lender.publicKey, 500000);
+    const loanId = await nextLoanId();
+    await program.methods
+      .submitBid(new anchor.BN(500000), rate, new anchor.BN(durationSlots), earlyRepayment, { cancelTaker: {} })
+      .accountsPartial(bidAccounts(lender.publicKey, lender.publicKey, rate, lenderTokenAccount))
+      .remainingAccounts([
+        writable(loanPda(loanId)),
+        writable(positionsPda(borrower.publicKey)),
+        writable(borrowerTokenAccount),
+      ])
+      .signers([lender])
+      .rpc();
+    return { loanId, borrower, lender, borrowerCollateralAccount, borrowerTokenAccount, lenderTokenAccount };
+  }
+
+  async function fetchEvents(tx: string) {
+    let signature;
+    for (let i = 0; i < 5; i++) {
+      signature = await provider.connection.getTransaction(tx, { commitment: "confirmed" });
+      if (signature) break;
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+    }
+    if (!signature) throw new Error("Failed to fetch transaction");
+
+    const eventParser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
+    return [...eventParser.parseLogs(signature.meta.logMessages)];
+  }
+
+  async function expectError(promise: Promise<unknown>, code: string) {
+    try {
+      await promise;
+    } catch (err) {
+      assert.equal(err.error?.errorCode?.code, code, `Expected ${code}, got ${err}`);
+      return;
+    }
+    assert.fail(`Expected ${code}`);
+  }
+
+  it("Configures the market, collateral mints and fee vault", async () => {
+    await program.methods
+      .configureMarket(
+        new anchor.BN(minAmount),
+        new anchor.BN(maxAmount),
+        new anchor.BN(3600),
+        10,
+        initialCollateralRatio
+      )
+      .accountsPartial({
+        lendAuction: lendAuctionPda,
+        market: marketPda(tokenMint),
+        admin: admin.publicKey,
+        tokenMint,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([adminSig])
+      .rpc();
+
+    for (const [mint, haircutBps] of [[collateralMint, 0], [basketMint, basketHaircutBps]] as [PublicKey, number][]) {
+      await program.methods
+        .configureCollateral(haircutBps, new anchor.BN(minCollateral), new anchor.BN(maxCollateral))
+        .accountsPartial({
+          lendAuction: lendAuctionPda,
+          collateralConfig: collateralConfigPda(mint),
+          admin: admin.publicKey,
+          mint,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([adminSig])
+        .rpc();
+    }
+
+    const feeVaultPda = pda(Buffer.from("fee_vault"), tokenMint.toBuffer());
+    await program.methods
+      .createFeeVault()
+      .accountsPartial({
+        lendAuction: lendAuctionPda,
+        feeVault: feeVaultPda,
+        admin: admin.publicKey,
+        mint: tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([adminSig])
+      .rpc();
+
+    const market = await program.account.market.fetch(marketPda(tokenMint));
+    assert.equal(market.minAmount.toNumber(), minAmount);
+    assert.equal(market.maxAmount.toNumber(), maxAmount);
+    assert.equal(market.initialCollateralRatio, initialCollateralRatio);
+
+    const collateralConfig = await program.account.collateralConfig.fetch(collateralConfigPda(collateralMint));
+    assert.equal(collateralConfig.minCollateral.toNumber(), minCollateral);
+    assert.equal(collateralConfig.maxCollateral.toNumber(), maxCollateral);
+    assert.equal(collateralConfig.decimals, 6);
+    const basketConfig = await program.account.collateralConfig.fetch(collateralConfigPda(basketMint));
+    assert.equal(basketConfig.haircutBps, basketHaircutBps);
+
+    const feeVault = await getAccount(provider.connection, feeVaultPda);
+    assert.equal(feeVault.mint.toBase58(), tokenMint.toBase58());
+    assert.equal(feeVault.owner.toBase58(), lendAuctionPda.toBase58());
+  });
+
   // it("Cleans up stale bids and asks with refunds and fees", async () => {
   //   await provider.connection.confirmTransaction(
   //     await provider.connection.requestAirdrop(admin.publicKey, 10 * LAMPORTS_PER_SOL),
@@ -528,191 +789,1023 @@ describe("contract", () => {
   //   assert.equal(bidSubmittedEvent.data.tokenMint.toBase58(), tokenMint.toBase58());
   // });
 
+  // Loan issued by the matching test, repaid by the repayment test
+  let issuedLoanId: anchor.BN;
+  let issuedBorrower: Keypair;
+  let issuedLender: PublicKey;
+
   it("Submits a bid with matching ask and issues a loan", async () => {
-    await provider.connection.confirmTransaction(
-      await provider.connection.requestAirdrop(admin.publicKey, 10 * LAMPORTS_PER_SOL),
-      "confirmed"
-    );
+    const rate = 5;
+    const { shardId, shardPool, loanPool } = shardAccounts(rate);
 
-    const [lendAuctionPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("lend_auction")],
-      program.programId
-    );
+    const asker = await fundedWallet();
+    const askerCollateralAccount = await tokenAccount(collateralMint, asker.publicKey, 2000000);
+    const borrowerTokenAccount = await tokenAccount(tokenMint, asker.publicKey);
 
-    const info = await provider.connection.getAccountInfo(lendAuctionPda);
-    if (!info) {
-      await program.methods
-        .initialize(new anchor.BN(1), [tokenMint, collateralMint])
-        .accounts({
-          admin: admin.publicKey,
-        })
-        .signers([admin])
-        .rpc();
-    }
+    // Rest an ask with collateral at the market's initial collateral ratio
+    await program.methods
+      .submitAsk(new anchor.BN(500000), rate, new anchor.BN(750000), [], { cancelTaker: {} })
+      .accountsPartial(askAccounts(asker.publicKey, asker.publicKey, rate, askerCollateralAccount, borrowerTokenAccount))
+      .signers([asker])
+      .rpc();
+    assert.equal(await balance(askerCollateralAccount), 1250000, "Collateral should move to the vault");
 
-    const shardCount = 1;
-    const minRate = 5;
-    const shardId = computeShardId(tokenMint, minRate, shardCount);
+    const bidder = await fundedWallet();
+    const bidderTokenAccount = await tokenAccount(tokenMint, bidder.publicKey, 1000000);
 
-    const [shardPoolPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("shard_pool"), shardId.toArrayLike(Buffer, "le", 8)],
-      program.programId
+    // The matched loan account, the borrower's positions and its proceeds account follow the bid
+    const loanId = await nextLoanId();
+    const loanPoolBefore = await program.account.loanPool.fetch(loanPool);
+    const tx = await program.methods
+      .submitBid(new anchor.BN(500000), rate, new anchor.BN(1000), { proRata: {} }, { cancelTaker: {} })
+      .accountsPartial(bidAccounts(bidder.publicKey, bidder.publicKey, rate, bidderTokenAccount))
+      .remainingAccounts([
+        writable(loanPda(loanId)),
+        writable(positionsPda(asker.publicKey)),
+        writable(borrowerTokenAccount),
+      ])
+      .signers([bidder])
+      .rpc();
+
+    await provider.connection.confirmTransaction(tx, "confirmed");
+
+    // Verify token transfers
+    assert.equal(await balance(bidderTokenAccount), 500000, "Bidder should have remaining tokens");
+    assert.equal(await balance(borrowerTokenAccount), 500000, "Borrower should receive loan amount");
+
+    // Verify loan state
+    const loan = await program.account.loan.fetch(loanPda(loanId));
+    assert.equal(loan.lender.toBase58(), bidder.publicKey.toBase58());
+    assert.equal(loan.borrower.toBase58(), asker.publicKey.toBase58());
+    assert.equal(loan.amount.toString(), "500000");
+    assert.equal(loan.rate, rate);
+    assert.equal(loan.collateral.toString(), "750000");
+    assert.ok(loan.status.active, "Loan should be active");
+
+    const loanPoolAfter = await program.account.loanPool.fetch(loanPool);
+    assert.equal(
+      loanPoolAfter.activeLoans.toNumber(),
+      loanPoolBefore.activeLoans.toNumber() + 1,
+      "One loan should be issued"
     );
-    const [loanPoolPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("loan_pool"), shardId.toArrayLike(Buffer, "le", 8)],
-      program.programId
+    const shardPoolAfter = await program.account.shardPool.fetch(shardPool);
+    assert.isFalse(
+      shardPoolAfter.asks.some((ask) => ask.borrower.equals(asker.publicKey)),
+      "Matched ask should leave the book"
     );
 
-    const asker = Keypair.generate();
+    // Verify both parties index the loan
+    const lenderPositions = await program.account.userPositions.fetch(positionsPda(bidder.publicKey));
+    const borrowerPositions = await program.account.userPositions.fetch(positionsPda(asker.publicKey));
+    assert.deepEqual(lenderPositions.loans.map((id) => id.toString()), [loanId.toString()]);
+    assert.deepEqual(borrowerPositions.loans.map((id) => id.toString()), [loanId.toString()]);
+
+    // Verify lend_auction total_loans
+    const lendAuction = await program.account.lendAuction.fetch(lendAuctionPda);
+    assert.equal(lendAuction.totalLoans.toString(), loanId.addn(1).toString(), "Total loans should increment");
+
+    // Verify event
+    const loanIssuedEvent = (await fetchEvents(tx)).find((event) => event.name === "loanIssued");
+    assert.ok(loanIssuedEvent, "LoanIssued event should be emitted");
+    assert.equal(loanIssuedEvent.data.loanId.toString(), loanId.toString());
+    assert.equal(loanIssuedEvent.data.lender.toBase58(), bidder.publicKey.toBase58());
+    assert.equal(loanIssuedEvent.data.borrower.toBase58(), asker.publicKey.toBase58());
+    assert.equal(loanIssuedEvent.data.amount.toString(), "500000");
+    assert.equal(loanIssuedEvent.data.rate, rate);
+    assert.equal(loanIssuedEvent.data.shardId.toString(), shardId.toString());
+    assert.equal(loanIssuedEvent.data.tokenMint.toBase58(), tokenMint.toBase58());
+    assert.equal(loanIssuedEvent.data.collateralMint.toBase58(), collateralMint.toBase58());
+
+    issuedLoanId = loanId;
+    issuedBorrower = asker;
+    issuedLender = bidder.publicKey;
+  });
+
+  it("Repays a loan and returns its collateral", async () => {
+    const borrower = issuedBorrower;
+    const borrowerTokenAccount = await tokenAccount(tokenMint, borrower.publicKey, 100000); // Covers interest
+    const borrowerCollateralAccount = await tokenAccount(collateralMint, borrower.publicKey);
+    const vaultBefore = await balance(vaultTokenAccount);
+
+    const tx = await program.methods
+      .repay(issuedLoanId)
+      .accountsPartial({
+        lendAuction: lendAuctionPda,
+        loan: loanPda(issuedLoanId),
+        market: marketPda(tokenMint),
+        lenderPositions: positionsPda(issuedLender),
+        borrowerPositions: positionsPda(borrower.publicKey),
+        authority: borrower.publicKey,
+        borrower: borrower.publicKey,
+        delegate: null,
+        borrowerTokenAccount,
+        borrowerCollateralAccount,
+        vaultTokenAccount,
+        vaultCollateralAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([borrower])
+      .rpc();
+
+    const loan = await program.account.loan.fetch(loanPda(issuedLoanId));
+    assert.ok(loan.status.repaid, "Loan should be repaid");
+    assert.isAtLeast(loan.claimable.toNumber(), 500000, "Repayment should be held for the lender");
+    assert.equal(await balance(vaultTokenAccount) - vaultBefore, loan.claimable.toNumber());
+    assert.equal(await balance(borrowerCollateralAccount), 2000000, "Collateral should be returned");
+
+    const borrowerPositions = await program.account.userPositions.fetch(positionsPda(borrower.publicKey));
+    assert.equal(borrowerPositions.loans.length, 0, "Repaid loan should leave the index");
+
+    const loanRepaidEvent = (await fetchEvents(tx)).find((event) => event.name === "loanRepaid");
+    assert.ok(loanRepaidEvent, "LoanRepaid event should be emitted");
+    assert.equal(loanRepaidEvent.data.payer.toBase58(), borrower.publicKey.toBase58());
+  });
+
+  it("Issues each loan into its own account by global loan id", async () => {
+    const rate = 12;
+    const asker = await fundedWallet();
+    const askerCollateralAccount = await tokenAccount(collateralMint, asker.publicKey, 750000);
+    const borrowerTokenAccount = await tokenAccount(tokenMint, asker.publicKey);
+    await program.methods
+      .submitAsk(new anchor.BN(500000), rate, new anchor.BN(750000), [], { cancelTaker: {} })
+      .accountsPartial(askAccounts(asker.publicKey, asker.publicKey, rate, askerCollateralAccount, borrowerTokenAccount))
+      .signers([asker])
+      .rpc();
+
+    const bidder = await fundedWallet();
+    const bidderTokenAccount = await tokenAccount(tokenMint, bidder.publicKey, 500000);
+    const loanId = await nextLoanId();
+    const matchingBid = (loanAccount: PublicKey) =>
+      program.methods
+        .submitBid(new anchor.BN(500000), rate, new anchor.BN(1000), { proRata: {} }, { cancelTaker: {} })
+        .accountsPartial(bidAccounts(bidder.publicKey, bidder.publicKey, rate, bidderTokenAccount))
+        .remainingAccounts([
+          writable(loanAccount),
+          writable(positionsPda(asker.publicKey)),
+          writable(borrowerTokenAccount),
+        ])
+        .signers([bidder])
+        .rpc();
+
+    // Only the PDA of the next global loan id can hold the loan
+    await expectError(matchingBid(loanPda(loanId.addn(1))), "InvalidLoanAccount");
+
+    // Lamports sent to the address ahead of time cannot block the loan from being created
     await provider.connection.confirmTransaction(
-      await provider.connection.requestAirdrop(asker.publicKey, 2 * LAMPORTS_PER_SOL),
+      await provider.connection.requestAirdrop(loanPda(loanId), LAMPORTS_PER_SOL / 1000),
       "confirmed"
     );
-    const askerCollateralAccount = await anchor.utils.token.associatedAddress({
-      mint: collateralMint,
-      owner: asker.publicKey,
-    });
-    await getOrCreateAssociatedTokenAccount(
-      provider.connection,
-      admin,
-      collateralMint,
-      asker.publicKey
+    await matchingBid(loanPda(loanId));
+
+    const loan = await program.account.loan.fetch(loanPda(loanId));
+    assert.equal(loan.loanId.toString(), loanId.toString());
+    assert.equal(loan.borrower.toBase58(), asker.publicKey.toBase58());
+    assert.ok(loan.status.active, "Loan should be active");
+    assert.equal(
+      (await program.account.lendAuction.fetch(lendAuctionPda)).totalLoans.toString(),
+      loanId.addn(1).toString()
     );
-    await mintTo(provider.connection, admin, collateralMint, askerCollateralAccount, admin, 2000000);
 
-    const vaultTokenAccount = await anchor.utils.token.associatedAddress({
-      mint: tokenMint,
-      owner: lendAuctionPda,
-    });
-    await getOrCreateAssociatedTokenAccount(
-      provider.connection,
-      admin,
+    // Earlier loans keep their own accounts
+    const earlier = await program.account.loan.fetch(loanPda(issuedLoanId));
+    assert.equal(earlier.loanId.toString(), issuedLoanId.toString());
+  });
+
+  it("Rejects orders outside the market and collateral size limits", async () => {
+    const rate = 10;
+    const trader = await fundedWallet();
+    const traderTokenAccount = await tokenAccount(tokenMint, trader.publicKey, 2 * maxAmount);
+    const traderCollateralAccount = await tokenAccount(collateralMint, trader.publicKey, 2 * maxCollateral);
+
+    for (const [amount, code] of [[minAmount - 1, "OrderTooSmall"], [maxAmount + 1, "OrderTooLarge"]] as [number, string][]) {
+      await expectError(
+        program.methods
+          .submitBid(new anchor.BN(amount), rate, new anchor.BN(1000), { proRata: {} }, { cancelTaker: {} })
+          .accountsPartial(bidAccounts(trader.publicKey, trader.publicKey, rate, traderTokenAccount))
+          .signers([trader])
+          .rpc(),
+        code
+      );
+    }
+
+    // Collateral limits are set per collateral mint, independently of the loan amount
+    for (const [collateral, code] of [[minCollateral - 1, "OrderTooSmall"], [maxCollateral + 1, "OrderTooLarge"]] as [number, string][]) {
+      await expectError(
+        program.methods
+          .submitAsk(new anchor.BN(minAmount), rate, new anchor.BN(collateral), [], { cancelTaker: {} })
+          .accountsPartial(askAccounts(trader.publicKey, trader.publicKey, rate, traderCollateralAccount, traderTokenAccount))
+          .signers([trader])
+          .rpc(),
+        code
+      );
+    }
+  });
+
+  it("Prevents self-trades by cancelling the taker or the resting order", async () => {
+    const rate = 20;
+    const { shardPool } = shardAccounts(rate);
+    const trader = await fundedWallet();
+    const traderTokenAccount = await tokenAccount(tokenMint, trader.publicKey, 1000000);
+    const traderCollateralAccount = await tokenAccount(collateralMint, trader.publicKey, 1000000);
+
+    await program.methods
+      .submitAsk(new anchor.BN(500000), rate, new anchor.BN(750000), [], { cancelTaker: {} })
+      .accountsPartial(askAccounts(trader.publicKey, trader.publicKey, rate, traderCollateralAccount, traderTokenAccount))
+      .signers([trader])
+      .rpc();
+
+    // CancelTaker drops the incoming bid and leaves the resting ask untouched
+    const cancelTakerTx = await program.methods
+      .submitBid(new anchor.BN(500000), rate, new anchor.BN(1000), { proRata: {} }, { cancelTaker: {} })
+      .accountsPartial(bidAccounts(trader.publicKey, trader.publicKey, rate, traderTokenAccount))
+      .signers([trader])
+      .rpc();
+    assert.equal(await balance(traderTokenAccount), 1000000, "Cancelled bid should not be funded");
+    let pool = await program.account.shardPool.fetch(shardPool);
+    assert.isTrue(pool.asks.some((ask) => ask.borrower.equals(trader.publicKey)), "Resting ask should remain");
+    assert.isFalse(pool.bids.some((bid) => bid.lender.equals(trader.publicKey)), "Bid should not rest");
+    const takerEvent = (await fetchEvents(cancelTakerTx)).find((event) => event.name === "selfTradePrevented");
+    assert.ok(takerEvent, "SelfTradePrevented event should be emitted");
+    assert.isFalse(takerEvent.data.resting);
+
+    // CancelResting refunds the resting ask, then the bid rests with nothing left to cross
+    const cancelRestingTx = await program.methods
+      .submitBid(new anchor.BN(500000), rate, new anchor.BN(1000), { proRata: {} }, { cancelResting: {} })
+      .accountsPartial({
+        ...bidAccounts(trader.publicKey, trader.publicKey, rate, traderTokenAccount),
+        bidderCollateralAccount: traderCollateralAccount,
+        vaultCollateralAccount,
+      })
+      .signers([trader])
+      .rpc();
+    assert.equal(await balance(traderCollateralAccount), 1000000, "Resting ask collateral should be refunded");
+    assert.equal(await balance(traderTokenAccount), 500000, "Resting bid should be funded");
+    pool = await program.account.shardPool.fetch(shardPool);
+    assert.isFalse(pool.asks.some((ask) => ask.borrower.equals(trader.publicKey)), "Resting ask should be cancelled");
+    assert.isTrue(pool.bids.some((bid) => bid.lender.equals(trader.publicKey)), "Bid should rest");
+    const restingEvent = (await fetchEvents(cancelRestingTx)).find((event) => event.name === "selfTradePrevented");
+    assert.ok(restingEvent, "SelfTradePrevented event should be emitted");
+    assert.isTrue(restingEvent.data.resting);
+    assert.equal(restingEvent.data.collateral.toString(), "750000");
+  });
+
+  it("Lets a delegate place orders within its spend cap", async () => {
+    const rate = 40;
+    const owner = await fundedWallet();
+    const delegate = await fundedWallet();
+    const ownerTokenAccount = await tokenAccount(tokenMint, owner.publicKey, 1000000);
+    const delegatePda = pda(Buffer.from("delegate"), owner.publicKey.toBuffer(), delegate.publicKey.toBuffer());
+
+    // Delegated spend moves under the lend auction's authority, capped per mint by the program
+    await approve(provider.connection, admin, ownerTokenAccount, lendAuctionPda, owner, 1000000);
+    const expirySlot = new anchor.BN((await provider.connection.getSlot()) + 100000);
+    await program.methods
+      .approveDelegate(delegate.publicKey, 1, [{ mint: tokenMint, cap: new anchor.BN(600000), spent: new anchor.BN(0) }], expirySlot)
+      .accountsPartial({
+        delegateAccount: delegatePda,
+        owner: owner.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+
+    const delegatedBid = (amount: number) =>
+      program.methods
+        .submitBid(new anchor.BN(amount), rate, new anchor.BN(1000), { proRata: {} }, { cancelTaker: {} })
+        .accountsPartial({
+          ...bidAccounts(owner.publicKey, delegate.publicKey, rate, ownerTokenAccount),
+          delegate: delegatePda,
+        })
+        .signers([delegate])
+        .rpc();
+
+    await delegatedBid(400000);
+    assert.equal(await balance(ownerTokenAccount), 600000, "Bid should be funded from the owner's account");
+    const pool = await program.account.shardPool.fetch(shardAccounts(rate).shardPool);
+    assert.isTrue(pool.bids.some((bid) => bid.lender.equals(owner.publicKey)), "Bid should rest for the owner");
+    const delegateAccount = await program.account.delegate.fetch(delegatePda);
+    assert.equal(delegateAccount.spendCaps[0].spent.toNumber(), 400000);
+
+    // The owner's token approval would allow it, but the delegate's cap does not
+    await expectError(delegatedBid(300000), "DelegateSpendCapExceeded");
+    assert.equal(await balance(ownerTokenAccount), 600000);
+
+    // Cancelling the delegate's bid refunds both the deposit and the delegate's cap
+    const { shardId, shardPool } = shardAccounts(rate);
+    const orderIdx = pool.bids.findIndex((bid) => bid.lender.equals(owner.publicKey));
+    assert.equal(pool.bids[orderIdx].delegate.toBase58(), delegatePda.toBase58());
+    await program.methods
+      .cancelOrder(shardId, { bid: {} }, new anchor.BN(orderIdx))
+      .accountsPartial({
+        lendAuction: lendAuctionPda,
+        shardPool,
+        authority: owner.publicKey,
+        owner: owner.publicKey,
+        delegate: null,
+        orderDelegate: delegatePda,
+        ownerPositions: positionsPda(owner.publicKey),
+        ownerTokenAccount,
+        vaultAccount: vaultTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([owner])
+      .rpc();
+    assert.equal(await balance(ownerTokenAccount), 1000000, "Cancelled bid should be refunded");
+    const refundedDelegate = await program.account.delegate.fetch(delegatePda);
+    assert.equal(refundedDelegate.spendCaps[0].spent.toNumber(), 0, "Cancelled bid should free the cap");
+
+    await delegatedBid(600000);
+    assert.equal(await balance(ownerTokenAccount), 400000, "Freed cap should fund a new bid");
+  });
+
+  it("Settles each signed order once and honours nonce cancellation", async () => {
+    const rate = 80;
+    const { shardPool, loanPool } = shardAccounts(rate);
+    const maker = await fundedWallet();
+    const taker = await fundedWallet();
+    const makerTokenAccount = await tokenAccount(tokenMint, maker.publicKey, 1000000);
+    const takerCollateralAccount = await tokenAccount(collateralMint, taker.publicKey, 2000000);
+    const takerTokenAccount = await tokenAccount(tokenMint, taker.publicKey);
+    const makerBalancePda = pda(Buffer.from("maker_balance"), maker.publicKey.toBuffer(), tokenMint.toBuffer());
+
+    // The lender pre-deposits the balance that funds its signed bids
+    await program.methods
+      .depositBalance(new anchor.BN(1000000))
+      .accountsPartial({
+        lendAuction: lendAuctionPda,
+        makerBalance: makerBalancePda,
+        owner: maker.publicKey,
+        ownerTokenAccount: makerTokenAccount,
+        vaultAccount: vaultTokenAccount,
+        mint: tokenMint,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([maker])
+      .rpc();
+
+    const expirySlot = new anchor.BN((await provider.connection.getSlot()) + 1000);
+    const signedOrder = (nonce: number) => ({
+      maker: maker.publicKey,
+      side: { bid: {} },
       tokenMint,
-      lendAuctionPda,
-      true
+      collateralMint,
+      amount: new anchor.BN(500000),
+      rate,
+      collateral: new anchor.BN(750000),
+      durationSlots: new anchor.BN(1000),
+      earlyRepayment: { proRata: {} },
+      nonce: new anchor.BN(nonce),
+      expirySlot,
+    });
+    const message = (order: ReturnType<typeof signedOrder>) =>
+      Buffer.concat([signedOrderDomain, program.programId.toBuffer(), program.coder.types.encode("signedOrder", order)]);
+
+    const settle = async (order: ReturnType<typeof signedOrder>, signed: Buffer = message(order)) =>
+      program.methods
+        .settleSignedOrder(order)
+        .accountsPartial({
+          lendAuction: lendAuctionPda,
+          market: marketPda(tokenMint),
+          collateralConfig: collateralConfigPda(collateralMint),
+          shardPool,
+          loanPool,
+          loan: loanPda(await nextLoanId()),
+          makerBalance: makerBalancePda,
+          orderFill: pda(Buffer.from("signed_order_fill"), makerBalancePda.toBuffer(), u64(order.nonce)),
+          makerPositions: positionsPda(maker.publicKey),
+          takerPositions: positionsPda(taker.publicKey),
+          taker: taker.publicKey,
+          takerFundingAccount: takerCollateralAccount,
+          borrowerTokenAccount: takerTokenAccount,
+          vaultTokenAccount,
+          vaultCollateralAccount,
+          instructionsSysvar: SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions([
+          Ed25519Program.createInstructionWithPrivateKey({ privateKey: maker.secretKey, message: signed }),
+        ])
+        .signers([taker])
+        .rpc();
+
+    // A signature over the bare order, without the domain and program id, is rejected
+    const order = signedOrder(3);
+    await expectError(settle(order, program.coder.types.encode("signedOrder", order)), "InvalidSignature");
+
+    const loanId = await nextLoanId();
+    const tx = await settle(order);
+    assert.equal(await balance(takerTokenAccount), 500000, "Borrower should receive loan amount");
+    assert.equal(await balance(takerCollateralAccount), 1250000, "Taker should post the collateral");
+    const loan = await program.account.loan.fetch(loanPda(loanId));
+    assert.equal(loan.lender.toBase58(), maker.publicKey.toBase58());
+    assert.equal(loan.borrower.toBase58(), taker.publicKey.toBase58());
+    let makerBalance = await program.account.makerBalance.fetch(makerBalancePda);
+    assert.equal(makerBalance.amount.toNumber(), 500000);
+    assert.equal(makerBalance.minNonce.toNumber(), 0, "Filling an order should not revoke the others");
+
+    // The shard pool is created alongside the loan pool so both can be closed later
+    const pool = await program.account.shardPool.fetch(shardPool);
+    assert.equal(pool.shardId.toString(), shardAccounts(rate).shardId.toString());
+    const settledEvent = (await fetchEvents(tx)).find((event) => event.name === "signedOrderSettled");
+    assert.ok(settledEvent, "SignedOrderSettled event should be emitted");
+    assert.equal(settledEvent.data.nonce.toNumber(), 3);
+
+    // Concurrent orders fill independently of the order they settle in, but each only once
+    await settle(signedOrder(1));
+    assert.equal(await balance(takerTokenAccount), 1000000, "Lower nonce should still fill");
+    makerBalance = await program.account.makerBalance.fetch(makerBalancePda);
+    assert.equal(makerBalance.amount.toNumber(), 0);
+    await expectError(settle(order), "NonceAlreadyUsed");
+    await expectError(settle(signedOrder(1)), "NonceAlreadyUsed");
+
+    // Cancelling nonces revokes every unfilled order below the new nonce
+    await program.methods
+      .cancelNonces(new anchor.BN(10))
+      .accountsPartial({ makerBalance: makerBalancePda, owner: maker.publicKey })
+      .signers([maker])
+      .rpc();
+    makerBalance = await program.account.makerBalance.fetch(makerBalancePda);
+    assert.equal(makerBalance.minNonce.toNumber(), 10);
+    await expectError(settle(signedOrder(5)), "NonceAlreadyUsed");
+    await expectError(
+      program.methods
+        .cancelNonces(new anchor.BN(10))
+        .accountsPartial({ makerBalance: makerBalancePda, owner: maker.publicKey })
+        .signers([maker])
+        .rpc(),
+      "NonceAlreadyUsed"
     );
+  });
 
-    const vaultCollateralAccount = await anchor.utils.token.associatedAddress({
-      mint: collateralMint,
-      owner: lendAuctionPda,
-    });
-    await getOrCreateAssociatedTokenAccount(
-      provider.connection,
-      admin,
-      collateralMint,
-      lendAuctionPda,
-      true
+  it("Counts basket collateral toward a loan's health before liquidation", async () => {
+    const rate = 60;
+    const borrower = await fundedWallet();
+    const borrowerCollateralAccount = await tokenAccount(collateralMint, borrower.publicKey, 1000000);
+    const borrowerBasketAccount = await tokenAccount(basketMint, borrower.publicKey, 1000000);
+    const borrowerTokenAccount = await tokenAccount(tokenMint, borrower.publicKey);
+    const configs = [readonly(collateralConfigPda(collateralMint)), readonly(collateralConfigPda(basketMint))];
+
+    // Primary collateral alone covers 100% of the loan; the basket, after its haircut, lifts it to 180%
+    const vaultBasketBefore = await balance(vaultBasketAccount);
+    await program.methods
+      .submitAsk(
+        new anchor.BN(1000000),
+        rate,
+        new anchor.BN(1000000),
+        [{ mint: basketMint, amount: new anchor.BN(1000000) }],
+        { cancelTaker: {} }
+      )
+      .accountsPartial(askAccounts(borrower.publicKey, borrower.publicKey, rate, borrowerCollateralAccount, borrowerTokenAccount))
+      .remainingAccounts([writable(borrowerBasketAccount), writable(vaultBasketAccount), ...configs])
+      .signers([borrower])
+      .rpc();
+    assert.equal(await balance(vaultBasketAccount) - vaultBasketBefore, 1000000, "Basket should move to the vault");
+
+    const lender = await fundedWallet();
+    const lenderTokenAccount = await tokenAccount(tokenMint, lender.publicKey, 1000000);
+    const loanId = await nextLoanId();
+    await program.methods
+      .submitBid(new anchor.BN(1000000), rate, new anchor.BN(1000), { proRata: {} }, { cancelTaker: {} })
+      .accountsPartial(bidAccounts(lender.publicKey, lender.publicKey, rate, lenderTokenAccount))
+      .remainingAccounts([
+        writable(loanPda(loanId)),
+        writable(positionsPda(borrower.publicKey)),
+        writable(borrowerTokenAccount),
+        ...configs,
+      ])
+      .signers([lender])
+      .rpc();
+
+    const loan = await program.account.loan.fetch(loanPda(loanId));
+    assert.equal(loan.collateral.toNumber(), 1000000);
+    assert.equal(loan.basket.length, 1);
+    assert.equal(loan.basket[0].mint.toBase58(), basketMint.toBase58());
+    assert.equal(loan.basket[0].amount.toNumber(), 1000000);
+
+    const loanInfo = await program.methods
+      .getLoan(loanId)
+      .accountsPartial({ loan: loanPda(loanId), market: marketPda(tokenMint) })
+      .remainingAccounts(configs)
+      .view();
+    assert.isAtLeast(loanInfo.healthFactor.toNumber(), 170, "Health should include the discounted basket");
+
+    // Sold collateral would be swapped on Raydium, but a loan healthy only through its basket
+    // is rejected before any swap is attempted
+    const liquidator = await fundedWallet();
+    const unused = () => Keypair.generate().publicKey;
+    await expectError(
+      program.methods
+        .liquidate(loanId, null, new anchor.BN(0))
+        .accountsPartial({
+          lendAuction: lendAuctionPda,
+          loan: loanPda(loanId),
+          market: marketPda(tokenMint),
+          lenderPositions: positionsPda(lender.publicKey),
+          borrowerPositions: positionsPda(borrower.publicKey),
+          liquidator: liquidator.publicKey,
+          liquidatorTokenAccount: await tokenAccount(tokenMint, liquidator.publicKey),
+          vaultTokenAccount,
+          vaultCollateralAccount,
+          vaultComponentAccount: null,
+          borrowerCollateralAccount: null,
+          raydiumAmmProgram: new PublicKey("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8"),
+          amm: unused(),
+          ammAuthority: unused(),
+          ammOpenOrders: unused(),
+          ammCoinVault: unused(),
+          ammPcVault: unused(),
+          marketProgram: unused(),
+          serumMarket: unused(),
+          marketBids: unused(),
+          marketAsks: unused(),
+          marketEventQueue: unused(),
+          marketCoinVault: unused(),
+          marketPcVault: unused(),
+          marketVaultSigner: unused(),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(configs)
+        .signers([liquidator])
+        .rpc(),
+      "LoanNotUnhealthy"
     );
+  });
 
-    const borrowerTokenAccount = await anchor.utils.token.associatedAddress({
-      mint: tokenMint,
-      owner: asker.publicKey,
-    });
-    await getOrCreateAssociatedTokenAccount(
-      provider.connection,
-      admin,
-      tokenMint,
-      asker.publicKey
+  it("Defaults a loan after maturity plus grace and lets the lender claim its collateral", async () => {
+    const { loanId, borrower, lender } = await issueLoan(30, 1);
+    const markDefaulted = () =>
+      program.methods
+        .markDefaulted(loanId)
+        .accountsPartial({ loan: loanPda(loanId), market: marketPda(tokenMint) })
+        .rpc();
+    const configureGracePeriod = (gracePeriod: number) =>
+      program.methods
+        .configureMarket(new anchor.BN(minAmount), new anchor.BN(maxAmount), new anchor.BN(gracePeriod), 10, initialCollateralRatio)
+        .accountsPartial({
+          lendAuction: lendAuctionPda,
+          market: marketPda(tokenMint),
+          admin: admin.publicKey,
+          tokenMint,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([adminSig])
+        .rpc();
+
+    // The loan matures within a slot, but the market's hour of grace still protects the borrower
+    await expectError(markDefaulted(), "GracePeriodActive");
+
+    await configureGracePeriod(0);
+    await sleep(2000);
+    const tx = await markDefaulted();
+    await configureGracePeriod(3600);
+
+    let loan = await program.account.loan.fetch(loanPda(loanId));
+    assert.ok(loan.status.defaulted, "Loan should be defaulted");
+    const defaultedEvent = (await fetchEvents(tx)).find((event) => event.name === "loanDefaulted");
+    assert.ok(defaultedEvent, "LoanDefaulted event should be emitted");
+    assert.equal(defaultedEvent.data.loanId.toString(), loanId.toString());
+    assert.equal(defaultedEvent.data.collateral.toNumber(), 750000);
+    await expectError(markDefaulted(), "InvalidLoanStatus");
+
+    const claimCollateral = (claimer: Keypair, lenderCollateralAccount: PublicKey) =>
+      program.methods
+        .claimCollateral(loanId)
+        .accountsPartial({
+          lendAuction: lendAuctionPda,
+          loan: loanPda(loanId),
+          loanPool: shardAccounts(30).loanPool,
+          loanPayer: loan.payer,
+          lenderPositions: positionsPda(lender.publicKey),
+          borrowerPositions: positionsPda(borrower.publicKey),
+          lender: claimer.publicKey,
+          positionTokenAccount: null,
+          lenderCollateralAccount,
+          vaultCollateralAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([claimer])
+        .rpc();
+
+    // Only the lender side may take the collateral
+    const stranger = await fundedWallet();
+    await expectError(
+      claimCollateral(stranger, await tokenAccount(collateralMint, stranger.publicKey)),
+      "Unauthorized"
     );
 
-    // Pre-populate an ask (assuming SubmitAsk exists with similar structure)
+    const lenderCollateralAccount = await tokenAccount(collateralMint, lender.publicKey);
+    await claimCollateral(lender, lenderCollateralAccount);
+    assert.equal(await balance(lenderCollateralAccount), 750000, "Lender should receive the collateral");
+    assert.isNull(await provider.connection.getAccountInfo(loanPda(loanId)), "Claimed loan should be closed");
+    const lenderPositions = await program.account.userPositions.fetch(positionsPda(lender.publicKey));
+    assert.isFalse(lenderPositions.loans.some((id) => id.eq(loanId)), "Claimed loan should leave the index");
+  });
+
+  it("Tops up the collateral of an open loan", async () => {
+    const { loanId, borrower, borrowerCollateralAccount } = await issueLoan(50);
+    await tokenAccount(collateralMint, borrower.publicKey, 250000);
+    const addCollateral = (signer: Keypair, signerCollateralAccount: PublicKey) =>
+      program.methods
+        .addCollateral(loanId, new anchor.BN(250000))
+        .accountsPartial({
+          lendAuction: lendAuctionPda,
+          loan: loanPda(loanId),
+          borrowerPositions: positionsPda(borrower.publicKey),
+          borrower: signer.publicKey,
+          borrowerCollateralAccount: signerCollateralAccount,
+          vaultCollateralAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([signer])
+        .rpc();
+
+    const stranger = await fundedWallet();
+    await expectError(
+      addCollateral(stranger, await tokenAccount(collateralMint, stranger.publicKey, 250000)),
+      "Unauthorized"
+    );
+
+    const vaultBefore = await balance(vaultCollateralAccount);
+    const tx = await addCollateral(borrower, borrowerCollateralAccount);
+    assert.equal(await balance(borrowerCollateralAccount), 0);
+    assert.equal(await balance(vaultCollateralAccount) - vaultBefore, 250000, "Top-up should move to the vault");
+    const loan = await program.account.loan.fetch(loanPda(loanId));
+    assert.equal(loan.collateral.toNumber(), 1000000);
+
+    const addedEvent = (await fetchEvents(tx)).find((event) => event.name === "collateralAdded");
+    assert.ok(addedEvent, "CollateralAdded event should be emitted");
+    assert.equal(addedEvent.data.amount.toNumber(), 250000);
+    assert.equal(addedEvent.data.collateral.toNumber(), 1000000);
+  });
+
+  it("Withdraws only the collateral above the market's initial ratio", async () => {
+    const { loanId, borrower, borrowerCollateralAccount } = await issueLoan(70);
+    const withdrawCollateral = (amount: number) =>
+      program.methods
+        .withdrawCollateral(loanId, new anchor.BN(amount))
+        .accountsPartial({
+          lendAuction: lendAuctionPda,
+          loan: loanPda(loanId),
+          market: marketPda(tokenMint),
+          borrowerPositions: positionsPda(borrower.publicKey),
+          borrower: borrower.publicKey,
+          borrowerCollateralAccount,
+          vaultCollateralAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([borrower])
+        .rpc();
+
+    // Issued at exactly 150%, accrued interest leaves nothing to spare
+    await expectError(withdrawCollateral(1), "InsufficientCollateral");
+
+    // After a top-up of 300000, the excess above 150% of the debt can come back out
+    await tokenAccount(collateralMint, borrower.publicKey, 300000);
     await program.methods
-      .submitAsk(new anchor.BN(500000), minRate, new anchor.BN(750000)) // Collateral = 1.5x amount
-      .accounts({
-        shardPool: shardPoolPda,
-        loanPool: loanPoolPda,
-        asker: asker.publicKey,
-        askerCollateralAccount: askerCollateralAccount,
-        borrowerTokenAccount: borrowerTokenAccount,
-        vaultTokenAccount: vaultTokenAccount,
-        vaultCollateralAccount: vaultCollateralAccount,
-        tokenMint: tokenMint,
-        collateralMint: collateralMint,
+      .addCollateral(loanId, new anchor.BN(300000))
+      .accountsPartial({
+        lendAuction: lendAuctionPda,
+        loan: loanPda(loanId),
+        borrowerPositions: positionsPda(borrower.publicKey),
+        borrower: borrower.publicKey,
+        borrowerCollateralAccount,
+        vaultCollateralAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
       })
-      .signers([asker])
+      .signers([borrower])
       .rpc();
+    await expectError(withdrawCollateral(350000), "InsufficientCollateral");
+    assert.equal(await balance(borrowerCollateralAccount), 0, "Rejected withdrawal should move nothing");
+
+    const tx = await withdrawCollateral(200000);
+    assert.equal(await balance(borrowerCollateralAccount), 200000);
+    const loan = await program.account.loan.fetch(loanPda(loanId));
+    assert.equal(loan.collateral.toNumber(), 850000);
+    const withdrawnEvent = (await fetchEvents(tx)).find((event) => event.name === "collateralWithdrawn");
+    assert.ok(withdrawnEvent, "CollateralWithdrawn event should be emitted");
+    assert.isAtLeast(withdrawnEvent.data.healthFactor.toNumber(), initialCollateralRatio);
+  });
 
-    const bidder = Keypair.generate();
-    await provider.connection.confirmTransaction(
-      await provider.connection.requestAirdrop(bidder.publicKey, 2 * LAMPORTS_PER_SOL),
-      "confirmed"
-    );
-    const bidderTokenAccount = await anchor.utils.token.associatedAddress({
-      mint: tokenMint,
-      owner: bidder.publicKey,
-    });
-    await getOrCreateAssociatedTokenAccount(
-      provider.connection,
-      admin,
-      tokenMint,
-      bidder.publicKey
+  it("Refinances a loan into a resting bid without moving its collateral", async () => {
+    const { loanId, borrower, lender, borrowerCollateralAccount } = await issueLoan(90);
+
+    // The payoff includes interest, so it needs more than the collateral issued at 150%
+    await tokenAccount(collateralMint, borrower.publicKey, 250000);
+    await program.methods
+      .addCollateral(loanId, new anchor.BN(250000))
+      .accountsPartial({
+        lendAuction: lendAuctionPda,
+        loan: loanPda(loanId),
+        borrowerPositions: positionsPda(borrower.publicKey),
+        borrower: borrower.publicKey,
+        borrowerCollateralAccount,
+        vaultCollateralAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([borrower])
+      .rpc();
+
+    const rate = 85;
+    const newLender = await fundedWallet();
+    const newLenderTokenAccount = await tokenAccount(tokenMint, newLender.publicKey, 1000000);
+    await program.methods
+      .submitBid(new anchor.BN(1000000), rate, new anchor.BN(1000), { proRata: {} }, { cancelTaker: {} })
+      .accountsPartial(bidAccounts(newLender.publicKey, newLender.publicKey, rate, newLenderTokenAccount))
+      .signers([newLender])
+      .rpc();
+
+    const { shardId, shardPool, loanPool } = shardAccounts(rate);
+    const pool = await program.account.shardPool.fetch(shardPool);
+    const bidIdx = new anchor.BN(pool.bids.findIndex((bid) => bid.lender.equals(newLender.publicKey)));
+    const vaultCollateralBefore = await balance(vaultCollateralAccount);
+    const newLoanId = await nextLoanId();
+    const refinance = (maxRate: number) =>
+      program.methods
+        .refinance(loanId, shardId, bidIdx, maxRate)
+        .accountsPartial({
+          lendAuction: lendAuctionPda,
+          market: marketPda(tokenMint),
+          loan: loanPda(loanId),
+          loanPool,
+          newLoan: loanPda(newLoanId),
+          shardPool,
+          borrower: borrower.publicKey,
+          borrowerPositions: positionsPda(borrower.publicKey),
+          lenderPositions: positionsPda(lender.publicKey),
+          newLender: newLender.publicKey,
+          newLenderPositions: positionsPda(newLender.publicKey),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([borrower])
+        .rpc();
+
+    await expectError(refinance(rate - 1), "RateTooHigh");
+    const tx = await refinance(rate);
+
+    const oldLoan = await program.account.loan.fetch(loanPda(loanId));
+    const newLoan = await program.account.loan.fetch(loanPda(newLoanId));
+    assert.ok(oldLoan.status.repaid, "Old loan should be paid off");
+    assert.isAbove(oldLoan.claimable.toNumber(), 500000, "Old lender should be owed principal and interest");
+    assert.ok(newLoan.status.active, "New loan should be active");
+    assert.equal(newLoan.lender.toBase58(), newLender.publicKey.toBase58());
+    assert.equal(newLoan.borrower.toBase58(), borrower.publicKey.toBase58());
+    assert.equal(newLoan.amount.toString(), oldLoan.claimable.toString(), "New loan should fund the payoff");
+    assert.equal(newLoan.rate, rate);
+    assert.equal(newLoan.collateral.toNumber(), 1000000);
+    assert.equal(await balance(vaultCollateralAccount), vaultCollateralBefore, "Collateral should stay in the vault");
+
+    const restingBid = (await program.account.shardPool.fetch(shardPool)).bids.find((bid) =>
+      bid.lender.equals(newLender.publicKey)
     );
-    await mintTo(provider.connection, admin, tokenMint, bidderTokenAccount, admin, 1000000);
+    assert.equal(restingBid.amount.toNumber(), 1000000 - newLoan.amount.toNumber(), "Bid remainder should rest");
 
-    // Get loan count
-    const loanPoolBefore = await program.account.loanPool.fetch(loanPoolPda);
-    const loanCount = loanPoolBefore.loans.length; 
+    const borrowerPositions = await program.account.userPositions.fetch(positionsPda(borrower.publicKey));
+    assert.deepEqual(borrowerPositions.loans.map((id) => id.toString()), [newLoanId.toString()]);
+    const refinancedEvent = (await fetchEvents(tx)).find((event) => event.name === "loanRefinanced");
+    assert.ok(refinancedEvent, "LoanRefinanced event should be emitted");
+    assert.equal(refinancedEvent.data.payoff.toString(), newLoan.amount.toString());
+  });
+
+  it("Extends a loan co-signed by its lender and borrower", async () => {
+    const { loanId, borrower, lender } = await issueLoan(100);
+    const before = await program.account.loan.fetch(loanPda(loanId));
+    const extendLoan = (lenderSigner: Keypair, newMaturityTs: anchor.BN, newRate: number) =>
+      program.methods
+        .extendLoan(loanId, newMaturityTs, newRate)
+        .accountsPartial({
+          loan: loanPda(loanId),
+          market: marketPda(tokenMint),
+          lender: lenderSigner.publicKey,
+          positionTokenAccount: null,
+          borrower: borrower.publicKey,
+        })
+        .signers([lenderSigner, borrower])
+        .rpc();
+
+    const newMaturityTs = before.maturityTs.addn(86400);
+    await expectError(extendLoan(await fundedWallet(), newMaturityTs, 110), "Unauthorized");
+    await expectError(extendLoan(lender, before.maturityTs, 110), "InvalidDuration");
+
+    const tx = await extendLoan(lender, newMaturityTs, 110);
+    const loan = await program.account.loan.fetch(loanPda(loanId));
+    assert.equal(loan.maturityTs.toString(), newMaturityTs.toString());
+    assert.equal(loan.rate, 110);
+    assert.equal(loan.amount.toString(), before.amount.toString(), "Principal should be unchanged");
+    assert.isAtLeast(loan.lastAccrualTs.toNumber(), before.lastAccrualTs.toNumber(), "Checkpoint should not rewind");
+    assert.equal(loan.accruedPenalty.toNumber(), 0, "A loan extended before maturity owes no penalty");
+
+    const extendedEvent = (await fetchEvents(tx)).find((event) => event.name === "loanExtended");
+    assert.ok(extendedEvent, "LoanExtended event should be emitted");
+    assert.equal(extendedEvent.data.oldRate, 100);
+    assert.equal(extendedEvent.data.newRate, 110);
+    assert.equal(extendedEvent.data.oldMaturityTs.toString(), before.maturityTs.toString());
+    assert.equal(extendedEvent.data.accruedInterest.toString(), loan.accruedInterest.toString());
+  });
+
+  it("Pays a tokenized loan's repayment to the holder of its position token", async () => {
+    const { loanId, borrower, lender, borrowerTokenAccount, borrowerCollateralAccount } = await issueLoan(120);
+    const positionMint = pda(Buffer.from("position_mint"), u64(loanId));
+    const lenderPositionAccount = getAssociatedTokenAddressSync(positionMint, lender.publicKey);
     const tx = await program.methods
-      .submitBid(new anchor.BN(500000), minRate, new anchor.BN(1000))
-      .accounts({
-        shardPool: shardPoolPda,
-        loanPool: loanPoolPda,
-        bidder: bidder.publicKey,
-        bidderTokenAccount: bidderTokenAccount,
-        borrowerTokenAccount: borrowerTokenAccount,
-        vaultTokenAccount: vaultTokenAccount,
-        tokenMint: tokenMint,
+      .tokenizeLoan(loanId)
+      .accountsPartial({
+        lendAuction: lendAuctionPda,
+        loan: loanPda(loanId),
+        positionMint,
+        lenderPositionAccount,
+        lender: lender.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
       })
-      .signers([bidder])
+      .signers([lender])
       .rpc();
 
-    await provider.connection.confirmTransaction(tx, "confirmed");
+    // The position is a fixed-supply NFT held by the lender
+    const mint = await getMint(provider.connection, positionMint);
+    assert.equal(mint.supply.toString(), "1");
+    assert.equal(mint.decimals, 0);
+    assert.isNull(mint.mintAuthority, "Supply should be fixed");
+    assert.equal(await balance(lenderPositionAccount), 1);
+    const loan = await program.account.loan.fetch(loanPda(loanId));
+    assert.equal(loan.positionMint.toBase58(), positionMint.toBase58());
+    const tokenizedEvent = (await fetchEvents(tx)).find((event) => event.name === "loanTokenized");
+    assert.ok(tokenizedEvent, "LoanTokenized event should be emitted");
+
+    // The lender sells the position on
+    const buyer = await fundedWallet();
+    const buyerPositionAccount = await tokenAccount(positionMint, buyer.publicKey);
+    await transfer(provider.connection, admin, lenderPositionAccount, buyerPositionAccount, lender, 1);
+
+    await tokenAccount(tokenMint, borrower.publicKey, 100000); // Covers interest
+    await program.methods
+      .repay(loanId)
+      .accountsPartial({
+        lendAuction: lendAuctionPda,
+        loan: loanPda(loanId),
+        market: marketPda(tokenMint),
+        lenderPositions: positionsPda(lender.publicKey),
+        borrowerPositions: positionsPda(borrower.publicKey),
+        authority: borrower.publicKey,
+        borrower: borrower.publicKey,
+        delegate: null,
+        borrowerTokenAccount,
+        borrowerCollateralAccount,
+        vaultTokenAccount,
+        vaultCollateralAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([borrower])
+      .rpc();
+    const claimable = (await program.account.loan.fetch(loanPda(loanId))).claimable.toNumber();
+
+    const claim = (claimer: Keypair, lenderTokenAccount: PublicKey) =>
+      program.methods
+        .claim(loanId)
+        .accountsPartial({
+          lendAuction: lendAuctionPda,
+          loan: loanPda(loanId),
+          loanPool: shardAccounts(120).loanPool,
+          loanPayer: loan.payer,
+          lender: claimer.publicKey,
+          positionTokenAccount: buyerPositionAccount,
+          lenderTokenAccount,
+          vaultTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([claimer])
+        .rpc();
 
-    // Verify token transfers
-    const bidderBalance = await getAccount(provider.connection, bidderTokenAccount);
-    const vaultBalance = await getAccount(provider.connection, vaultTokenAccount);
-    const borrowerBalance = await getAccount(provider.connection, borrowerTokenAccount);
-    assert.equal(Number(bidderBalance.amount), 500000, "Bidder should have remaining tokens");
-    assert.equal(Number(vaultBalance.amount), 1500000, "Vault should be empty after loan");
-    assert.equal(Number(borrowerBalance.amount), 500000, "Borrower should receive loan amount");
-
-    // Verify loan_pool state
-    const loanPoolAfter = await program.account.loanPool.fetch(loanPoolPda);
-    assert.equal(loanPoolAfter.loans.length, loanCount+1, "One loan should be issued");
-    assert.equal(loanPoolAfter.loans[loanPoolAfter.loans.length-1].lender.toBase58(), bidder.publicKey.toBase58());
-    assert.equal(loanPoolAfter.loans[loanPoolAfter.loans.length-1].borrower.toBase58(), asker.publicKey.toBase58());
-    assert.equal(loanPoolAfter.loans[loanPoolAfter.loans.length-1].amount.toString(), "500000");
-    assert.equal(loanPoolAfter.loans[loanPoolAfter.loans.length-1].rate, minRate);
-    assert.equal(loanPoolAfter.loans[loanPoolAfter.loans.length-1].collateral.toString(), "750000");
+    // The originating lender no longer holds the position
+    await expectError(claim(lender, await tokenAccount(tokenMint, lender.publicKey)), "Unauthorized");
 
-    // Verify lend_auction total_loans
-    const lendAuction = await program.account.lendAuction.fetch(lendAuctionPda);
-    assert.equal(lendAuction.totalLoans.toString(), loanPoolAfter.loans.length.toString(), "Total loans should increment");
+    const buyerTokenAccount = await tokenAccount(tokenMint, buyer.publicKey);
+    await claim(buyer, buyerTokenAccount);
+    assert.isAtLeast(claimable, 500000);
+    assert.equal(await balance(buyerTokenAccount), claimable, "Holder should receive the repayment");
+  });
 
-    // Verify event
-    let signature;
-    for (let i = 0; i < 5; i++) {
-      signature = await provider.connection.getTransaction(tx, { commitment: "confirmed" });
-      if (signature) break;
-      await new Promise((resolve) => setTimeout(resolve, 1000));
-    }
-    if (!signature) throw new Error("Failed to fetch transaction");
+  it("Sends loan proceeds only to the payout account the borrower recorded", async () => {
+    const rate = 140;
+    const asker = await fundedWallet();
+    const askerCollateralAccount = await tokenAccount(collateralMint, asker.publicKey, 750000);
+    const borrowerTokenAccount = await tokenAccount(tokenMint, asker.publicKey);
+    const payoutTokenAccount = await createAccount(provider.connection, admin, tokenMint, asker.publicKey, Keypair.generate());
+    const submitAsk = (payout: PublicKey) =>
+      program.methods
+        .submitAsk(new anchor.BN(500000), rate, new anchor.BN(750000), [], { cancelTaker: {} })
+        .accountsPartial({
+          ...askAccounts(asker.publicKey, asker.publicKey, rate, askerCollateralAccount, borrowerTokenAccount),
+          payoutTokenAccount: payout,
+        })
+        .signers([asker])
+        .rpc();
 
-    const eventParser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl));
-    const events = eventParser.parseLogs(signature.meta.logMessages);
-    let loanIssuedEvent = null;
-    for (const event of events) {
-      if (event.name === "loanIssued") loanIssuedEvent = event;
-    }
+    // A payout account must belong to the asker
+    const stranger = await fundedWallet();
+    await expectError(submitAsk(await tokenAccount(tokenMint, stranger.publicKey)), "ConstraintRaw");
+    await submitAsk(payoutTokenAccount);
+
+    const bidder = await fundedWallet();
+    const bidderTokenAccount = await tokenAccount(tokenMint, bidder.publicKey, 500000);
+    const loanId = await nextLoanId();
+    const matchingBid = (proceedsAccount: PublicKey) =>
+      program.methods
+        .submitBid(new anchor.BN(500000), rate, new anchor.BN(1000), { proRata: {} }, { cancelTaker: {} })
+        .accountsPartial(bidAccounts(bidder.publicKey, bidder.publicKey, rate, bidderTokenAccount))
+        .remainingAccounts([
+          writable(loanPda(loanId)),
+          writable(positionsPda(asker.publicKey)),
+          writable(proceedsAccount),
+        ])
+        .signers([bidder])
+        .rpc();
 
-    assert.ok(loanIssuedEvent, "LoanIssued event should be emitted");
-    assert.equal(loanIssuedEvent.data.lender.toBase58(), bidder.publicKey.toBase58());
-    assert.equal(loanIssuedEvent.data.borrower.toBase58(), asker.publicKey.toBase58());
-    assert.equal(loanIssuedEvent.data.amount.toString(), "500000");
-    assert.equal(loanIssuedEvent.data.rate, minRate);
-    assert.equal(loanIssuedEvent.data.shardId.toString(), shardId.toString());
-    assert.equal(loanIssuedEvent.data.tokenMint.toBase58(), tokenMint.toBase58());
-    assert.equal(loanIssuedEvent.data.collateralMint.toBase58(), collateralMint.toBase58());
+    // Neither the lender's own account nor the borrower's default account can take the proceeds
+    await expectError(matchingBid(bidderTokenAccount), "MissingBorrowerAccount");
+    await expectError(matchingBid(borrowerTokenAccount), "MissingBorrowerAccount");
+
+    await matchingBid(payoutTokenAccount);
+    assert.equal(await balance(payoutTokenAccount), 500000, "Proceeds should go to the recorded payout");
+    assert.equal(await balance(borrowerTokenAccount), 0);
+    assert.equal(await balance(bidderTokenAccount), 0);
+  });
+
+  it("Only swaps a loan's collateral through the pinned Raydium program", async () => {
+    const { loanId, borrower, lender, borrowerCollateralAccount } = await issueLoan(160);
+    const raydiumAmmProgram = new PublicKey("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
+    const unused = () => Keypair.generate().publicKey;
+    const repayWithCollateral = (signer: Keypair, signerCollateralAccount: PublicKey, ammProgram: PublicKey) =>
+      program.methods
+        .repayWithCollateral(loanId, new anchor.BN(750000))
+        .accountsPartial({
+          lendAuction: lendAuctionPda,
+          loan: loanPda(loanId),
+          market: marketPda(tokenMint),
+          lenderPositions: positionsPda(lender.publicKey),
+          borrowerPositions: positionsPda(borrower.publicKey),
+          borrower: signer.publicKey,
+          borrowerCollateralAccount: signerCollateralAccount,
+          vaultTokenAccount,
+          vaultCollateralAccount,
+          raydiumAmmProgram: ammProgram,
+          amm: unused(),
+          ammAuthority: unused(),
+          ammOpenOrders: unused(),
+          ammCoinVault: unused(),
+          ammPcVault: unused(),
+          marketProgram: unused(),
+          serumMarket: unused(),
+          marketBids: unused(),
+          marketAsks: unused(),
+          marketEventQueue: unused(),
+          marketCoinVault: unused(),
+          marketPcVault: unused(),
+          marketVaultSigner: unused(),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([signer])
+        .rpc();
+
+    // A look-alike swap program could report a fill without paying, so it is refused outright
+    const vaultCollateralBefore = await balance(vaultCollateralAccount);
+    await expectError(repayWithCollateral(borrower, borrowerCollateralAccount, unused()), "ConstraintRaw");
+
+    // Only the borrower may sell its collateral, even through the real program
+    const stranger = await fundedWallet();
+    await expectError(
+      repayWithCollateral(stranger, await tokenAccount(collateralMint, stranger.publicKey), raydiumAmmProgram),
+      "Unauthorized"
+    );
+
+    assert.equal(await balance(vaultCollateralAccount), vaultCollateralBefore, "No collateral should leave the vault");
+    const loan = await program.account.loan.fetch(loanPda(loanId));
+    assert.ok(loan.status.active, "Loan should stay open");
+    assert.equal(loan.collateral.toNumber(), 750000);
+  });
+
+  it("Keeps shard pools open while they hold orders or loans", async () => {
+    const { shardId, shardPool, loanPool } = shardAccounts(10);
+    const pool = await program.account.shardPool.fetch(shardPool);
+    const loans = await program.account.loanPool.fetch(loanPool);
+    assert.isAbove(pool.bids.length + pool.asks.length, 0, "Shard should hold resting orders");
+
+    const closeEmptyPools = (shardPoolPayer: PublicKey, loanPoolPayer: PublicKey) =>
+      program.methods
+        .closeEmptyPools(shardId)
+        .accountsPartial({ lendAuction: lendAuctionPda, shardPool, loanPool, shardPoolPayer, loanPoolPayer })
+        .rpc();
+
+    // Rent only ever returns to whoever funded each pool
+    const stranger = Keypair.generate().publicKey;
+    await expectError(closeEmptyPools(stranger, loans.payer), "Unauthorized");
+    await expectError(closeEmptyPools(pool.payer, stranger), "Unauthorized");
+
+    await expectError(closeEmptyPools(pool.payer, loans.payer), "PoolNotEmpty");
+    assert.ok(await provider.connection.getAccountInfo(shardPool), "Shard pool should stay open");
+    assert.ok(await provider.connection.getAccountInfo(loanPool), "Loan pool should stay open");
   });
 
   // it("Submits an ask without matching bids", async () => {